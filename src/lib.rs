@@ -12,7 +12,7 @@
 //!
 //! Here is a minimal example that displays the range  `0..100` in a menu:
 //!
-//! ```rust
+//! ```ignore
 //! let mut menu = youchoose::Menu::new(0..100);
 //! let choice = menu.show();
 //! // `choice` is a Vec<usize> containing the chosen indices
@@ -29,7 +29,7 @@
 //! The `youchoose::Menu` has a preview feature, which executes a command and
 //! shows the results on a seperate pane.
 //!
-//! ```rust
+//! ```ignore
 //! use youchoose;
 //!
 //! fn main(){
@@ -53,11 +53,22 @@
 //!
 //! ![preview](https://raw.githubusercontent.com/nathom/youchoose/main/screenshots/with_preview.png)
 //!
+//! To preview items with the output of a shell command, use
+//! `shell_preview`. The item is passed as the last argument, and a failing
+//! command shows its error in the pane instead of crashing the menu.
+//!
+//! ```no_run
+//! let files = vec!["Cargo.toml", "README.md"];
+//! let mut menu = youchoose::Menu::new(files.into_iter())
+//!     .preview(youchoose::shell_preview("cat", &[]));
+//! let choice = menu.show();
+//! ```
+//!
 //! **Customization**
 //!
 //! Let's take a look at an example that showcases the available methods for customization.
 //!
-//! ```rust
+//! ```ignore
 //! use youchoose;
 //!
 //! fn main() {
@@ -83,13 +94,50 @@
 //!
 //! ![fully customized](https://raw.githubusercontent.com/nathom/youchoose/main/screenshots/customized.png)
 
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::ffi::CStr;
 use std::fmt;
-// use std::fs::OpenOptions;
-// use std::io::Write;
-use std::iter::Peekable;
+use std::io::{self, Write};
+use std::mem;
 use std::ops;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use ncurses::*;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// Smallest main pane that can still display an item
+const MIN_HEIGHT: i32 = 3;
+const MIN_WIDTH: i32 = 4;
+
+// First color pair used for `ItemStyle` colors
+const STYLE_PAIR_OFFSET: i16 = 16;
+// One pair for each (foreground, background) combination, including default
+const STYLE_PAIRS: i16 = 9 * 9;
+
+thread_local! {
+    // (foreground, background) of each `ItemStyle` pair initialized so far,
+    // starting at `STYLE_PAIR_OFFSET`
+    static STYLE_PAIR_COLORS: RefCell<Vec<(i16, i16)>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const SPINNER_INTERVAL_MS: i32 = 80;
+
+// Most items loaded to rank search matches, so that an endless iterator
+// doesn't hang the search
+const RANK_LIMIT: usize = 10_000;
+
+// With key acceleration, presses of the same navigation key closer together
+// than this count as one streak, which moves one more row every few presses
+const ACCEL_INTERVAL_MS: u64 = 60;
+const ACCEL_PRESSES_PER_ROW: u32 = 4;
+const ACCEL_MAX_ROWS: u32 = 10;
 
 /// A Menu that lazily displays an iterable and (optionally) its preview.
 pub struct Menu<'a, I, D>
@@ -97,16 +145,56 @@ where
     D: fmt::Display,
     I: Iterator<Item = D>,
 {
-    iter: Peekable<I>,
+    iter: I,
     screen: Screen,
     preview: Option<Preview<D>>,
+    // box listing the items picked with `transfer`, beside the menu
+    picked: Option<Screen>,
     item_icon: &'a str,
     chosen_item_icon: &'a str,
+    hover_item_icon: Option<&'a str>,
     selection: Vec<usize>,
     keys: Keys,
+    sort_by: Option<CmpFunc<D>>,
+    filters: Vec<Filter<D>>,
+    describe: Option<DescFunc<D>>,
+    search_key: Option<SearchFunc<D>>,
+    matcher: Option<MatchFunc>,
+    // values of the yielded items whose preview has not been computed yet,
+    // indexed like `state.items`
+    values: Vec<Option<D>>,
+    // misuse of the builders, reported by `try_show`
+    error: Option<MenuError>,
+    // keys bound to actions in a specific mode, checked before `keys`
+    mode_keys: Vec<(Mode, i32, Action)>,
+    on_close: Option<CloseFunc>,
+    // keys that run a function on the hovered item's text
+    action_keys: Vec<(i32, ActionFunc)>,
+    // original indices of the items that open a submenu, with its factory
+    submenus: Vec<(usize, SubmenuFunc)>,
+    // original indices chosen through the submenus, starting in this menu
+    path: Vec<usize>,
+    sections: Option<DepthFunc<D>>,
+    tick: Option<(Duration, TickFunc)>,
+    // (original index, chosen) of every toggle, with `record_history`
+    history: Vec<(usize, bool)>,
+    // original indices of the items shown first, under a header
+    recent: Vec<usize>,
+    // groups of toggles that can be undone, and undone ones to redo
+    undo: Vec<Vec<(usize, bool)>>,
+    redo: Vec<Vec<(usize, bool)>>,
+    // state to restore when the menu is shown
+    snapshot: Option<MenuSnapshot>,
+    title: Option<String>,
+    footer: Option<String>,
+    // number of items, if the iterator knows it up front
+    total: Option<usize>,
+    // takes the next item without waiting for it, if the iterator is read
+    // on a worker thread
+    poll: Option<PollFunc<I, D>>,
 
     state: MenuState<'a>,
-    config: MenuConfig,
+    config: MenuSettings,
 }
 
 enum MenuReturnCode {
@@ -126,625 +214,6200 @@ where
     pub fn new(iter: I) -> Menu<'a, I, D> {
         let screen = Screen::new(ScreenSide::Full, 0.5);
 
-        let item_icon: &'a str = "❯";
+        // Garbled icons are worse than plain ones
+        let item_icon: &'a str = if supports_unicode() { "❯" } else { ">" };
         let chosen_item_icon: &'a str = "*";
 
         Menu {
-            iter: iter.peekable(),
+            iter,
             screen,
             preview: None,
-            item_icon: &item_icon,
-            chosen_item_icon: &chosen_item_icon,
+            picked: None,
+            item_icon,
+            chosen_item_icon,
+            hover_item_icon: None,
             selection: Vec::new(),
 
-            keys: Keys {
-                down: vec![KEY_DOWN, 'j' as i32],
-                up: vec![KEY_UP, 'k' as i32],
-                select: vec![10],
-                multiselect: vec![32],
-            },
+            keys: Keys::default(),
 
-            state: MenuState {
-                hover: 0,
-                start: 0,
-                items: Vec::new(),
-            },
+            sort_by: None,
+            filters: Vec::new(),
+            describe: None,
+            search_key: None,
+            matcher: None,
+            values: Vec::new(),
+            error: None,
+            mode_keys: Vec::new(),
+            on_close: None,
+            action_keys: Vec::new(),
+            submenus: Vec::new(),
+            path: Vec::new(),
+            sections: None,
+            tick: None,
+            history: Vec::new(),
+            recent: Vec::new(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            snapshot: None,
+            title: None,
+            footer: None,
+            total: None,
+            poll: None,
+
+            state: MenuState::new(),
+
+            config: MenuSettings::default(),
+        }
+    }
 
-            config: MenuConfig { multiselect: false },
+    /// Forgets the items read from the iterator so far, the selection and
+    /// the search query, and scrolls back to the top, so that the menu can
+    /// be shown again. Settings, keybindings and filters are kept. Items are
+    /// read from the iterator lazily, so the ones it has not yielded yet are
+    /// shown next, starting from index `0`; see `set_items` to show new
+    /// items instead.
+    pub fn reset(&mut self) {
+        let prepared = self.state.prepared;
+        let focused = self.state.focused;
+        self.state = MenuState::new();
+        self.state.prepared = prepared;
+        self.state.focused = focused;
+        self.selection.clear();
+        self.values.clear();
+        self.history.clear();
+        self.undo.clear();
+        self.redo.clear();
+        self.path.clear();
+        // The length of the iterator is no longer the number of items
+        self.total = None;
+        if prepared {
+            self.yield_sorted();
+            self.redraw();
         }
     }
 
+    /// Replaces the items of the menu with those of `iter` and resets it,
+    /// see `reset`. This lets one menu be shown for different data.
+    ///
+    /// ```no_run
+    /// let mut menu = youchoose::Menu::new(vec![1, 2, 3].into_iter());
+    /// let first = menu.show();
+    /// menu.set_items(vec![4, 5, 6].into_iter());
+    /// let second = menu.show();
+    /// ```
+    pub fn set_items(&mut self, iter: I) {
+        self.iter = iter;
+        self.reset();
+    }
+
+    /// Like `new`, but uses the length of the iterator for the scrollbar
+    /// instead of waiting until every item has been loaded.
+    pub fn new_sized(iter: I) -> Menu<'a, I, D>
+    where
+        I: ExactSizeIterator,
+    {
+        let total = iter.len();
+        let mut menu = Menu::new(iter);
+        menu.total = Some(total);
+        menu
+    }
+
     /// Initialize curses and display the menu on the screen.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the menu could not be shown. Use `try_show` to handle the
+    /// error instead.
     pub fn show(&mut self) -> Vec<usize> {
-        init_curses();
+        self.try_show().unwrap_or_else(|err| panic!("{}", err))
+    }
 
-        self.screen.show();
-        if let Some(prev) = &mut self.preview {
-            prev.show();
+    /// Initialize curses and display the menu on the screen, returning an
+    /// error if the terminal could not be set up or the menu was
+    /// misconfigured. Curses is never left initialized when an error is
+    /// returned.
+    ///
+    /// ```no_run
+    /// match youchoose::Menu::new(0..100).try_show() {
+    ///     Ok(choice) => println!("Chose {:?}", choice),
+    ///     Err(err) => eprintln!("Could not show menu: {}", err),
+    /// }
+    /// ```
+    pub fn try_show(&mut self) -> Result<Vec<usize>, MenuError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if self.accept_initial() {
+            let chosen = self.selection();
+            if let Some(on_close) = &self.on_close {
+                on_close(&chosen);
+            }
+            return Ok(chosen);
         }
-        self.refresh();
-
-        loop {
-            match self.screen.get_key() {
-                27 | 113 => break, // ESC or q
-
-                val => {
-                    // This will erase the entire window
-                    self.screen.erase();
 
-                    match self.handle_key(val) {
-                        Pass => {
-                            self.refresh();
-                        }
-                        Done => break,
-                    }
-                }
+        let mut selection = Vec::new();
+        for event in self.events()? {
+            if let MenuEvent::Chosen(chosen) | MenuEvent::Cancelled(chosen) =
+                event
+            {
+                selection = chosen;
             }
         }
+        Ok(selection)
+    }
+
+    /// Shows the menu and returns an iterator over what happens in it, for
+    /// callers that want to run code between keypresses. Each call to
+    /// `next` waits for a key, handles it, redraws the menu and describes
+    /// the result. The last event is `Chosen` or `Cancelled`, after which
+    /// the terminal is restored. Dropping the iterator early also restores
+    /// the terminal.
+    ///
+    /// ```no_run
+    /// use youchoose::MenuEvent;
+    ///
+    /// let mut menu = youchoose::Menu::new(0..100);
+    /// for event in menu.events().unwrap() {
+    ///     match event {
+    ///         MenuEvent::Moved(index) => eprintln!("Hovering {}", index),
+    ///         MenuEvent::Chosen(choice) => println!("Chose {:?}", choice),
+    ///         _ => {}
+    ///     }
+    /// }
+    /// ```
+    pub fn events(&mut self) -> Result<Events<'_, 'a, I, D>, MenuError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
 
-        end_curses();
-        self.finish()
+        let curses = self.open_curses()?;
+        self.prepare(curses.frame);
+        // A borrowed screen still shows whatever was drawn on it before
+        self.screen.erase();
+        self.refresh();
+        Ok(Events {
+            menu: self,
+            curses: Some(curses),
+        })
     }
 
-    fn finish(&self) -> Vec<usize> {
-        self.selection.clone()
+    /// The original index of the hovered item.
+    fn hovered_index(&self) -> Option<usize> {
+        self.current().map(|i| self.state.items[i].index)
     }
 
-    fn yield_item(&mut self, i: usize) -> Option<&Item> {
-        while self.state.items.len() <= i {
-            if let Some(item) = self.iter.next() {
-                let mut new_item =
-                    Item::new(&item, self.item_icon, self.chosen_item_icon);
-                if let Some(preview) = &self.preview {
-                    new_item.preview(item, &preview.func);
-                }
-                self.state.items.push(new_item);
-            } else {
-                return None;
-            }
+    /// Borrows the host's curses session or starts one, as configured.
+    fn open_curses(&self) -> Result<Curses, MenuError> {
+        if self.config.borrow_curses {
+            return Ok(Curses {
+                host: Some(borrow_curses()?),
+                session: None,
+                frame: None,
+            });
         }
-        Some(&self.state.items[i])
-    }
 
-    fn refresh(&mut self) {
-        // Maximum index that will fit on current screen state
-        let end = self.state.start + self.screen.max_y();
-        self.yield_item(end);
+        // Curses can't draw to or read from a pipe
+        let tty = unsafe {
+            libc::isatty(libc::STDIN_FILENO) == 1
+                && libc::isatty(libc::STDOUT_FILENO) == 1
+        };
+        if !tty {
+            return Err(MenuError::NotATty);
+        }
+        let frame = match self.config.inline {
+            Some(rows) => Some(reserve_inline_rows(rows)?),
+            None => None,
+        };
+        let session = init_curses()?;
+        if frame.is_some() {
+            stay_on_main_screen();
+        }
+        Ok(Curses {
+            host: None,
+            session: Some(session),
+            frame,
+        })
+    }
 
-        self.screen.reset_pos();
+    /// Lays out the panes in the given frame and loads the first items.
+    fn prepare(&mut self, frame: Option<(i32, i32)>) {
+        self.screen.set_frame(frame);
         if let Some(prev) = &mut self.preview {
-            prev.draw_box();
-            prev.screen.reset_pos();
+            prev.set_frame(frame);
         }
-        let mut i = self.state.start;
-        let pos = self.state.hover + i;
-        while let Some(item) = self.state.items.get(i) {
-            if !self.screen.write_item(&item, pos == i) {
-                break;
-            }
-            if pos == i {
-                if let Some(prev) = &mut self.preview {
-                    prev.screen.addstr(item.preview.as_ref().unwrap());
-                }
-            }
-
-            i += 1;
+        if let Some(picked) = &mut self.picked {
+            picked.set_frame(frame);
         }
+        self.reserve_space();
 
-        self.screen.refresh();
-
+        self.screen.show();
         if let Some(prev) = &mut self.preview {
-            prev.refresh();
+            prev.show();
+        }
+        let snapshot = self.snapshot.take();
+        if let Some(snapshot) = &snapshot {
+            self.restore_selection(&snapshot.selection);
+        }
+        self.yield_sorted();
+        if let Some(&last) = self.recent.iter().max() {
+            // The recent items are shown first, so they are needed up front
+            self.yield_item(last);
+            self.pin_visible();
         }
+        let select_all =
+            self.config.start_all_selected && self.config.multiselect;
+        if select_all && snapshot.is_none() {
+            self.select_all();
+        }
+        if self.ranking() {
+            // Sort the items matching the initial query
+            self.apply_filters();
+        }
+        if let Some(snapshot) = snapshot {
+            self.restore_hover(snapshot.hover, snapshot.start);
+        }
+        if let Some((interval, _)) = &self.tick {
+            self.state.next_tick = Some(Instant::now() + *interval);
+        }
+        self.state.prepared = true;
     }
 
-    fn handle_key(&mut self, val: i32) -> RetCode {
-        if self.keys.down.contains(&val) {
-            self.move_selection(1)
-        } else if self.keys.up.contains(&val) {
-            self.move_selection(-1)
-        } else if self.config.multiselect
-            && self.keys.multiselect.contains(&val)
-        {
-            self.multiselect_item()
-        } else if self.keys.select.contains(&val) {
-            self.select_item()
-        } else {
-            Pass
+    /// Draws the menu on the screen. Together with `handle_key`, this lets
+    /// the menu be driven by an event loop of its own, instead of `show`.
+    /// Curses must already be initialized, and the menu is drawn on the
+    /// whole screen. If colors have been started, the first draw sets up
+    /// the color pairs used by the menu.
+    ///
+    /// ```no_run
+    /// # ncurses::initscr();
+    /// # ncurses::start_color();
+    /// let mut menu = youchoose::Menu::new(0..100);
+    /// menu.redraw();
+    /// while menu.handle_key(ncurses::getch()) {
+    ///     menu.redraw();
+    /// }
+    /// let choice = menu.selection();
+    /// # ncurses::endwin();
+    /// ```
+    pub fn redraw(&mut self) {
+        if !self.state.prepared {
+            if has_colors() {
+                init_colors();
+            }
+            self.prepare(None);
+        }
+        if let Some(from) = self.state.moved_from.take() {
+            if self.config.minimal_redraw && self.redraw_hover(from) {
+                return;
+            }
         }
+        // This will erase the entire window
+        self.screen.erase();
+        self.refresh();
     }
 
-    fn select_item(&mut self) -> RetCode {
-        let curr_item_idx = self.state.start + self.state.hover;
-        match self.selection.last() {
-            Some(&num) if num == curr_item_idx => return Done,
-            _ => (),
+    /// Handles a key read from curses, returning whether the menu should
+    /// keep going. `ERR`, as returned when `getch` times out, advances the
+    /// loading spinner.
+    pub fn handle_key(&mut self, key: i32) -> bool {
+        // Moving the hover only changes part of the menu, unless a status
+        // message is cleared or a tick changes something as well
+        self.state.moved_from = None;
+        let (status, ticks) = (self.state.status.is_some(), self.state.ticks);
+        let keep_going = self.handle_key_inner(key);
+        if (status && key != ERR) || self.state.ticks != ticks {
+            self.state.moved_from = None;
         }
-        self.state.items[curr_item_idx].select();
-        self.selection.push(curr_item_idx);
-        Done
+        keep_going
     }
 
-    fn multiselect_item(&mut self) -> RetCode {
-        let curr_item_idx = self.state.start + self.state.hover;
-        let curr_item = &mut self.state.items[curr_item_idx];
-        curr_item.select();
-
-        let item_idx_pos =
-            match self.selection.iter().position(|x| *x == curr_item_idx) {
-                Some(idx) => idx as i32,
-                None => -1,
-            };
-
-        if curr_item.chosen() && item_idx_pos == -1 {
-            self.selection.push(curr_item_idx);
-        } else if !curr_item.chosen() && item_idx_pos != -1 {
-            self.selection.remove(item_idx_pos as usize);
+    fn handle_key_inner(&mut self, key: i32) -> bool {
+        if key != ERR {
+            self.state.status = None;
+        }
+        self.run_tick();
+        match key {
+            key if key != ERR && self.state.confirming.is_some() => {
+                self.process_confirm_key(key)
+            }
+            // ESC or q
+            27 | 113
+                if self.state.mode == Mode::Normal
+                    && !self.state.quicklook =>
+            {
+                self.state.quit = true;
+                false
+            }
+            ERR => {
+                self.state.spinner += 1;
+                true
+            }
+            key => {
+                let before = if self.config.confirm {
+                    Some((self.selection.clone(), self.undo.len()))
+                } else {
+                    None
+                };
+                match self.process_key(key) {
+                    Pass => true,
+                    Done if !self.selection.is_empty() && before.is_some() => {
+                        self.state.confirming = before;
+                        true
+                    }
+                    Done => false,
+                }
+            }
         }
-
-        Pass
     }
 
-    fn scroll(&mut self, amount: i32) {
-        self.state.start = ((self.state.start as i32) + amount) as usize;
-        assert!(self.state.start < 1_000_000);
+    /// Handles a key while the choice is waiting to be confirmed. Going
+    /// back to the list undoes the choice.
+    fn process_confirm_key(&mut self, key: i32) -> bool {
+        const Y: i32 = 'y' as i32;
+        const N: i32 = 'n' as i32;
+        match key {
+            Y => false,
+            N | 27 => {
+                if let Some((selection, undo)) = self.state.confirming.take() {
+                    for item in &mut self.state.items {
+                        item.chosen = selection.contains(&item.index);
+                    }
+                    self.selection = selection;
+                    self.undo.truncate(undo);
+                    self.after_toggles();
+                }
+                true
+            }
+            _ => true,
+        }
     }
 
-    fn move_selection(&mut self, amount: i32) -> RetCode {
-        let num_items = self.screen.items_on_screen as f64;
-        let new_hover = ((self.state.hover as i32) + amount) as f64;
-
-        if new_hover < 0.0 || (new_hover - num_items).abs() < f64::EPSILON {
-            return Pass;
+    /// How long to wait for a key, in milliseconds, before something needs
+    /// to be updated: loading streamed items, a debounced preview or a tick.
+    /// `-1` waits indefinitely.
+    fn poll_delay(&self) -> i32 {
+        let spinner = Duration::from_millis(SPINNER_INTERVAL_MS as u64);
+        let loading = self.config.streaming && self.loading();
+        // Round up so the deadline has passed when the wait is over
+        let waits = [
+            if loading { Some(spinner) } else { None },
+            self.preview_wait()
+                .map(|wait| wait + Duration::from_millis(1)),
+            self.preview_poll(),
+            self.state
+                .next_tick
+                .map(|tick| tick.saturating_duration_since(Instant::now())),
+        ];
+        match waits.iter().flatten().min() {
+            Some(wait) => wait.as_millis().min(i32::MAX as u128) as i32,
+            None => -1,
         }
+    }
 
-        self.state.hover = new_hover as usize;
-
-        if new_hover > num_items * 0.67
-            && self.state.start + self.screen.items_on_screen
-                < self.state.items.len()
-        {
-            self.scroll(1);
-            self.state.hover -= 1;
-        } else if new_hover < num_items * 0.33
-            && self.state.start > 0
-            && amount < 0
-        {
-            self.scroll(-1);
-            self.state.hover += 1;
+    /// Calls the tick callback if its interval has passed.
+    fn run_tick(&mut self) {
+        let (interval, func) = match &mut self.tick {
+            Some((interval, func)) => (*interval, func),
+            None => return,
+        };
+        let now = Instant::now();
+        match self.state.next_tick {
+            Some(next) if now < next => return,
+            _ => self.state.next_tick = Some(now + interval),
         }
 
-        Pass
+        self.state.ticks += 1;
+        let mut context = TickContext {
+            ticks: self.state.ticks,
+            status: self.state.status.take(),
+            title: self.title.take(),
+        };
+        func(&mut context);
+        self.state.status = context.status;
+        self.title = context.title;
     }
 
-    /// Add a preview pane that displays the result of applying the function
-    /// passed in to each item in the iterable. The function must return a
-    /// String.
-    pub fn preview<F>(mut self, func: F) -> Menu<'a, I, D>
-    where
-        F: Fn(D) -> String + 'static,
-    {
-        let func = DispFunc::new(Box::new(func));
-        self.screen.set_pos(ScreenSide::Left, 0.5);
-        self.preview = Some(Preview::new(func, ScreenSide::Right, 0.5));
-        self
+    /// Consume the menu, display it, and return the chosen indices. This is
+    /// the same as `show`, but allows the whole menu to be built and shown in
+    /// one expression.
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(0..100).multiselect().run();
+    /// ```
+    pub fn run(mut self) -> Vec<usize> {
+        self.show()
     }
 
-    /// Sets the position of the preview pane. The `side` parameter determines
-    /// the side on which the pane sits. The `width` parameter is a float between
-    /// `0.0` and `1.0`, inclusive. It determines the proportion of the screen that
-    /// the preview pane should use.
+    /// Returns the hover position and selection of the menu, e.g. to
+    /// restore it with `restore_snapshot` the next time the program runs.
     ///
-    /// The menu's side is automatically switched to opposite the preview pane's side,
-    /// and the menu's width is set to `1 - width`.
-    pub fn preview_pos(
+    /// ```no_run
+    /// let mut menu = youchoose::Menu::new(0..100).multiselect();
+    /// menu.show();
+    /// let snapshot = menu.state_snapshot();
+    ///
+    /// // Later on
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .multiselect()
+    ///     .restore_snapshot(snapshot)
+    ///     .show();
+    /// ```
+    pub fn state_snapshot(&self) -> MenuSnapshot {
+        MenuSnapshot {
+            hover: self.state.hover,
+            start: self.state.start,
+            selection: self.selection.clone(),
+        }
+    }
+
+    /// Restores a snapshot taken with `state_snapshot` when the menu is
+    /// shown. The selection is only restored in multiselect mode, and
+    /// positions past the end of the items are clamped.
+    pub fn restore_snapshot(
         mut self,
-        side: ScreenSide,
-        width: f64,
+        snapshot: MenuSnapshot,
     ) -> Menu<'a, I, D> {
-        self.screen.set_pos(!side, 1.0 - width);
-        self.preview
-            .as_mut()
-            .expect("Must create preview before settting it's position")
-            .set_pos(side, width);
-
+        self.snapshot = Some(snapshot);
         self
     }
 
-    /// Sets the default icon of the menu. This is displayed before each entry.
-    pub fn icon(mut self, icon: &'a str) -> Menu<'a, I, D> {
-        self.item_icon = icon;
-        self
+    fn restore_selection(&mut self, selection: &[usize]) {
+        if !self.config.multiselect {
+            return;
+        }
+        self.selection = selection.to_vec();
+        for item in &mut self.state.items {
+            item.chosen = self.selection.contains(&item.index);
+        }
     }
 
-    /// Sets the icon displayed when an item is selected in multiselect mode.
-    pub fn selected_icon(mut self, icon: &'a str) -> Menu<'a, I, D> {
-        self.chosen_item_icon = icon;
-        self
+    fn restore_hover(&mut self, hover: usize, start: usize) {
+        self.yield_visible(start + hover);
+        let last = self.state.visible.len().saturating_sub(1);
+        let mut start = start.min(last);
+        let mut hover = hover.min(last - start);
+
+        // Keep the hovered item on the screen
+        self.screen.layout();
+        let bounds = &self.screen.bounds;
+        let rows = (bounds.1.y - bounds.0.y - 1).max(1) as usize;
+        if hover >= rows {
+            start += hover + 1 - rows;
+            hover = rows - 1;
+        }
+        self.state.start = start;
+        self.state.hover = hover;
     }
 
-    /// Sets the text displayed on top of the preview box. It is recommended to surround the label
-    /// with spaces for aesthetic reasons. If it is not set, `" preview "` will be used.
-    pub fn preview_label(mut self, label: String) -> Menu<'a, I, D> {
-        self.preview
-            .as_mut()
-            .expect("Must create preview before settting it's position")
-            .set_label(label);
-        self
+    /// Returns the chosen indices, in the order given by `selection_order`.
+    /// If the menu was quit with nothing chosen, the indices given by
+    /// `default_on_quit` are returned instead.
+    pub fn selection(&self) -> Vec<usize> {
+        if self.state.quit && self.selection.is_empty() {
+            let item = match self.config.default_on_quit {
+                DefaultChoice::Empty => None,
+                DefaultChoice::Hovered => self.current(),
+                DefaultChoice::First => self.state.visible.first().copied(),
+                DefaultChoice::Index(index) => return vec![index],
+            };
+            return item
+                .map(|i| self.state.items[i].index)
+                .into_iter()
+                .collect();
+        }
+        match self.config.selection_order {
+            Order::AsToggled => self.selection.clone(),
+            Order::ListOrder => self
+                .state
+                .items
+                .iter()
+                .map(|item| item.index)
+                .filter(|index| self.selection.contains(index))
+                .collect(),
+        }
     }
 
-    /// Adds a keybinding that triggers a multiselection. This inputs an `ncurses` keycode.
-    /// All ascii keys can be set by passing the character as an `i32`. The keycodes for
-    /// special keys can be found by importing `ncurses` and using the provided constants
-    /// or by testing with the `getch` function. For example, running the following will display
-    /// the keycodes on the screen.
-    ///
-    /// ```
-    /// // use ncurses::*;
-    ///
-    /// initscr();
-    /// loop {
-    ///     let c: i32 = getch();
-    ///     clear();
-    ///     if c == 'q' as i32 {break}
-    ///     addstr(&format!("Pressed key with keycode {}!", c.to_string()));
-    /// }
-    /// endwin();
-    /// ```
-    pub fn add_multiselect_key(mut self, key: i32) -> Menu<'a, I, D> {
-        self.keys.multiselect.push(key);
-        self
+    /// Leaves out the indices in `selection` that don't belong to an item,
+    /// for the methods that return something of each chosen item. Only an
+    /// index given with `DefaultChoice::Index` can be such an index.
+    fn item_indices(&self, selection: Vec<usize>) -> Vec<usize> {
+        let loaded = self.state.items.len();
+        selection.into_iter().filter(|&i| i < loaded).collect()
     }
 
-    /// Adds a keybinding that triggers an up movement. See [`add_multiselect_key`](struct.Menu.html#method.add_multiselect_key) for more information.
-    pub fn add_up_key(mut self, key: i32) -> Menu<'a, I, D> {
-        self.keys.up.push(key);
-        self
+    /// Reads the rest of the iterator and selects every item.
+    fn select_all(&mut self) {
+        while self.yield_item(self.state.items.len()).is_some() {}
+        for item in &mut self.state.items {
+            item.chosen = true;
+            self.selection.push(item.index);
+        }
+        if self.config.pin_selected {
+            self.apply_filters();
+        }
     }
 
-    /// Adds a keybinding that triggers a down movement. See [`add_multiselect_key`](struct.Menu.html#method.add_multiselect_key) for more information.
-    pub fn add_down_key(mut self, key: i32) -> Menu<'a, I, D> {
-        self.keys.down.push(key);
-        self
+    fn yield_item(&mut self, i: usize) -> Option<&Item<'_>> {
+        while self.state.items.len() <= i {
+            if let Some(item) = self.iter.next() {
+                let index = self.state.items.len();
+                self.push_item(index, item);
+            } else {
+                self.state.exhausted = true;
+                return None;
+            }
+        }
+        Some(&self.state.items[i])
     }
 
-    /// Adds a keybinding that triggers a selection. See [`add_multiselect_key`](struct.Menu.html#method.add_multiselect_key) for more information.
-    pub fn add_select_key(mut self, key: i32) -> Menu<'a, I, D> {
-        self.keys.select.push(key);
-        self
+    /// Yields items until the visible row `row` exists. Returns whether it
+    /// does.
+    fn yield_visible(&mut self, row: usize) -> bool {
+        while self.state.visible.len() <= row {
+            let next = self.state.items.len();
+            if self.yield_item(next).is_none() {
+                return false;
+            }
+        }
+        true
     }
 
-    /// Allow multiple items to be selected from the menu.
-    pub fn multiselect(mut self) -> Menu<'a, I, D> {
-        self.config.multiselect = true;
-        self
+    /// Yields items needed to fill the screen until `budget` runs out. Items
+    /// read on a worker thread are added as far as they have arrived instead.
+    fn yield_for(&mut self, budget: Duration) {
+        let end = self.state.start + self.screen.max_y();
+        if let Some(poll) = self.poll {
+            while self.state.visible.len() <= end {
+                match poll(&mut self.iter) {
+                    Ok(item) => {
+                        let index = self.state.items.len();
+                        self.push_item(index, item);
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.state.exhausted = true;
+                        break;
+                    }
+                }
+            }
+            return;
+        }
+        let deadline = Instant::now() + budget;
+        while self.state.visible.len() <= end && Instant::now() < deadline {
+            let next = self.state.items.len();
+            if self.yield_item(next).is_none() {
+                break;
+            }
+        }
     }
-}
 
-struct MenuState<'a> {
-    hover: usize,
-    start: usize,
-    items: Vec<Item<'a>>,
-}
+    /// The number of items passing the filters, if it is known without
+    /// draining the iterator.
+    fn visible_total(&self) -> Option<usize> {
+        if self.state.exhausted {
+            return Some(self.state.visible.len());
+        }
+        if self.filtered() {
+            None
+        } else {
+            self.total
+        }
+    }
 
-struct Keys {
-    down: Vec<i32>,
-    up: Vec<i32>,
-    select: Vec<i32>,
-    multiselect: Vec<i32>,
-}
-
-struct MenuConfig {
-    multiselect: bool,
-}
+    /// Whether a search query or filter is hiding items.
+    fn filtered(&self) -> bool {
+        !self.state.query.is_empty()
+            || self.filters.iter().any(|filter| filter.active)
+    }
 
-struct Screen {
-    bounds: (Pair, Pair),
-    pos: Pair,
-    items_on_screen: usize,
-    side: ScreenSide,
-    width: f64,
-}
+    /// Draws the arrows of `scroll_indicators` on the empty rows above and
+    /// below the items, at the right edge of the pane.
+    fn draw_scroll_indicators(&mut self) {
+        // Only loading the next item tells whether there is one
+        let end = self.state.start + self.screen.items_on_screen;
+        let below = self.yield_visible(end);
+        let bounds = &self.screen.bounds;
+        let x = if self.config.rtl {
+            bounds.0.x
+        } else {
+            bounds.1.x - 1
+        };
+        attron(A_DIM());
+        if self.state.start > 0 {
+            mvaddstr(bounds.0.y, x, "▲");
+        }
+        if below {
+            mvaddstr(bounds.1.y - 1, x, "▼");
+        }
+        attroff(A_DIM());
+    }
 
-impl Screen {
-    fn new(side: ScreenSide, width: f64) -> Screen {
-        assert!(width > 0.0 && width <= 1.0);
+    /// Draws the scrollbar in the column to the right of the items. If the
+    /// total is unknown, the thumb is placed relative to the items loaded so
+    /// far and a `?` marks the end of the track.
+    fn draw_scrollbar(&mut self) {
+        let bounds = &self.screen.bounds;
+        let (top, x) = (bounds.0.y + 1, bounds.1.x);
+        let height = bounds.1.y - top - 1;
+        let shown = self.screen.items_on_screen;
+        let total = self.visible_total();
+        let known = total.unwrap_or(self.state.visible.len());
+        if height < 1 || (total.is_some() && known <= shown) {
+            return;
+        }
 
-        let bounds = (Pair { y: 0, x: 0 }, Pair { y: 0, x: 0 });
-        let pos = Pair { y: 0, x: 0 };
+        let known = known.max(1) as i32;
+        let thumb = (height * shown as i32 / known).max(1).min(height);
+        let offset =
+            (height * self.state.start as i32 / known).min(height - thumb);
 
-        Screen {
-            bounds,
-            pos,
-            items_on_screen: 0,
-            side,
-            width,
+        attron(A_DIM());
+        for y in 0..height {
+            mvaddstr(top + y, x, "│");
+        }
+        attroff(A_DIM());
+        for y in offset..offset + thumb {
+            mvaddstr(top + y, x, "┃");
+        }
+        if total.is_none() {
+            mvaddstr(top + height - 1, x, "?");
         }
     }
 
-    fn show(&mut self) {
-        self.bounds = self
-            .side
-            .get_bounds((Pair { y: 0, x: 0 }, Self::get_size()), self.width);
+    /// Whether the screen is still waiting for items from the iterator.
+    fn loading(&self) -> bool {
+        !self.state.exhausted
+            && self.state.visible.len()
+                <= self.state.start + self.screen.max_y()
     }
 
-    fn write_item(&mut self, item: &Item, highlight: bool) -> bool {
-        self.skiplines(1);
-
-        if self.pos.y >= self.bounds.1.y - 1 {
-            return false;
+    fn push_item(&mut self, index: usize, item: D) {
+        let mut new_item =
+            Item::new(&item, index, self.item_icon, self.chosen_item_icon);
+        new_item.filter_hits = self
+            .filters
+            .iter()
+            .map(|filter| (filter.pred)(&item))
+            .collect();
+        if let Some(describe) = &self.describe {
+            new_item.desc = describe(&item);
+        }
+        if let Some(search_key) = &self.search_key {
+            new_item.search_repr = Some(search_key(&item));
+        }
+        let chosen =
+            self.config.multiselect && self.selection.contains(&index);
+        new_item.chosen = chosen;
+        if let Some(depth) = &self.sections {
+            new_item.depth = depth(&item);
+            // The section is headed by the closest earlier item that is less
+            // deeply nested
+            let mut parent = self.state.items.len().checked_sub(1);
+            while let Some(i) = parent {
+                if self.state.items[i].depth < new_item.depth {
+                    self.state.items[i].has_children = true;
+                    break;
+                }
+                parent = self.state.items[i].parent;
+            }
+            new_item.parent = parent;
+        }
+        if self.is_shown(&new_item) {
+            self.state.visible.push(self.state.items.len());
         }
+        self.state.items.push(new_item);
+        if chosen {
+            self.pin_visible();
+        }
+        // Previews are computed once the item is hovered
+        let value = if self.preview.is_some() {
+            Some(item)
+        } else {
+            None
+        };
+        self.values.push(value);
+    }
 
-        let icon_color = if item.chosen() { 3 } else { 2 };
+    /// Returns the position of the item whose preview should be drawn,
+    /// computing the hovered item's preview once the debounce interval has
+    /// passed. Until then, the previously shown preview is kept.
+    fn update_preview(&mut self) -> Option<usize> {
+        let i = self.current()?;
+        if self.state.hovered != Some(i) {
+            self.state.hovered = Some(i);
+            self.state.hover_since = Instant::now();
+        }
+        if self.state.items[i].preview.is_none() {
+            if self.manual_pending() {
+                return None;
+            }
+            if self.preview_wait().is_some() {
+                return self.state.shown_preview;
+            }
+            self.load_preview(i)?;
+        }
+        self.state.shown_preview = Some(i);
+        Some(i)
+    }
 
-        attron(COLOR_PAIR(icon_color));
-        attron(A_BOLD());
+    /// Whether the hovered item's preview waits for the preview key, with
+    /// `manual_preview`.
+    fn manual_pending(&self) -> bool {
+        match (&self.preview, self.current()) {
+            (Some(prev), Some(i)) => {
+                let item = &self.state.items[i];
+                prev.manual && item.preview.is_none() && item.pending.is_none()
+            }
+            _ => false,
+        }
+    }
 
-        self.addstr(item.icon());
-        self.addch(' ');
+    /// Computes the preview of the item at position `i` in `items`, unless
+    /// it already has one, and picks it up once a worker thread is done.
+    fn load_preview(&mut self, i: usize) -> Option<()> {
+        let item = &self.state.items[i];
+        if item.preview.is_none() && item.pending.is_none() {
+            let preview = self.preview.as_ref()?;
+            let value = self.values[i].take()?;
+            let item = &mut self.state.items[i];
+            match preview.eval(value, &item.repr) {
+                PreviewJob::Ready(text) => item.preview = Some(text),
+                PreviewJob::Running(receiver) => {
+                    item.pending = Some(PendingPreview {
+                        receiver,
+                        since: Instant::now(),
+                        failed: false,
+                    })
+                }
+            }
+        }
+        let item = &mut self.state.items[i];
+        if let Some(pending) = &mut item.pending {
+            match pending.receiver.try_recv() {
+                Ok(text) => {
+                    item.preview = Some(text);
+                    item.pending = None;
+                }
+                Err(TryRecvError::Disconnected) => pending.failed = true,
+                Err(TryRecvError::Empty) => (),
+            }
+        }
+        Some(())
+    }
 
-        attroff(A_BOLD());
-        attroff(COLOR_PAIR(icon_color));
+    /// The message shown instead of the preview of the item at position `i`
+    /// while a worker thread is computing it, or after it failed to.
+    fn preview_placeholder(&self, i: usize) -> Option<&'static str> {
+        let pending = self.state.items[i].pending.as_ref()?;
+        let timeout = self.preview.as_ref()?.func.timeout;
+        Some(if pending.failed {
+            "preview failed"
+        } else if timeout.is_some_and(|t| pending.since.elapsed() >= t) {
+            "preview timed out"
+        } else {
+            "loading preview"
+        })
+    }
 
-        if highlight {
-            attron(COLOR_PAIR(1));
+    /// How long to wait before checking on the hovered item's preview again,
+    /// while it is being computed and hasn't timed out.
+    fn preview_poll(&self) -> Option<Duration> {
+        let pending = self.state.items[self.current()?].pending.as_ref()?;
+        if pending.failed {
+            return None;
         }
+        let poll = Duration::from_millis(SPINNER_INTERVAL_MS as u64);
+        match self.preview.as_ref()?.func.timeout {
+            // Round up so the timeout has passed when the wait is over
+            Some(timeout) => timeout
+                .checked_sub(pending.since.elapsed())
+                .map(|left| poll.min(left + Duration::from_millis(1))),
+            None => Some(poll),
+        }
+    }
 
-        self.addstr(item.string());
+    /// Draws the hovered item's preview in a box over most of the menu.
+    /// Returns where a raw preview should be written instead.
+    fn draw_quicklook(&mut self) -> Option<(i32, i32)> {
+        let i = self.current()?;
+        self.load_preview(i)?;
+        let label = self.preview.as_ref().and_then(|prev| prev.label.clone());
 
-        if highlight {
-            attroff(COLOR_PAIR(1));
-        }
+        // Leave a tenth of the frame around the box
+        let (top_left, bottom_right) = self.screen.frame_bounds();
+        let margin_y = (bottom_right.y - top_left.y) / 10;
+        let margin_x = (bottom_right.x - top_left.x) / 10;
+        let mut overlay = Screen::new(ScreenSide::Full, 1.0);
+        overlay.bounds = (
+            Pair {
+                y: top_left.y + margin_y,
+                x: top_left.x + margin_x,
+            },
+            Pair {
+                y: bottom_right.y - margin_y,
+                x: bottom_right.x - margin_x,
+            },
+        );
 
-        self.items_on_screen += 1;
+        // Blank out the menu under the box
+        let width = (overlay.bounds.1.x - overlay.bounds.0.x).max(0) as usize;
+        for row in overlay.bounds.0.y..overlay.bounds.1.y {
+            mvaddstr(row, overlay.bounds.0.x, &" ".repeat(width));
+        }
+        let box_style = self.config.box_style;
+        box_style.on();
+        overlay.draw_box(ScreenSide::Full, 1.0, &label);
+        box_style.off();
 
-        true
+        overlay.bounds.0.y += 1;
+        overlay.bounds.0.x += 1;
+        overlay.bounds.1.y = (overlay.bounds.1.y - 1).max(overlay.bounds.0.y);
+        overlay.bounds.1.x = (overlay.bounds.1.x - 1).max(overlay.bounds.0.x);
+        overlay.reset_pos();
+        if self.raw_preview() {
+            return Some((overlay.bounds.0.y, overlay.bounds.0.x));
+        }
+        if let Some(placeholder) = self.preview_placeholder(i) {
+            overlay.write_centered(placeholder);
+        } else if let Some(text) = &self.state.items[i].preview {
+            overlay.addstr_styled(text);
+        }
+        None
     }
 
-    fn draw_box(
-        &mut self,
-        side: ScreenSide,
-        width: f64,
-        label: &Option<String>,
-    ) {
-        let bounds = side
-            .get_bounds((self.bounds.0.clone(), self.bounds.1.clone()), width);
+    /// Draws the chosen items in a box in the middle of the menu, with the
+    /// question below them.
+    fn draw_confirm(&mut self) {
+        let question = "Confirm? (y/n)";
+        let mut lines: Vec<String> = self
+            .selection()
+            .into_iter()
+            .filter_map(|i| self.repr_of(i))
+            .map(|repr| repr.lines().next().unwrap_or("").to_string())
+            .collect();
 
-        let box_width = (bounds.1.x - bounds.0.x) as usize;
-        // let box_height = (bounds.1.y - bounds.0.y) as usize;
+        let (top_left, bottom_right) = self.screen.frame_bounds();
+        let frame_height = bottom_right.y - top_left.y;
+        let frame_width = bottom_right.x - top_left.x;
+        // The border, a blank line and the question take four rows
+        let room = (frame_height - 4).max(1) as usize;
+        if lines.len() > room {
+            let more = lines.len() - room + 1;
+            lines.truncate(room - 1);
+            lines.push(format!("… and {} more", more));
+        }
+        let widest = lines
+            .iter()
+            .map(|line| UnicodeWidthStr::width(line.as_str()))
+            .chain(Some(question.len()))
+            .max()
+            .unwrap_or(0) as i32;
+        let width = (widest + 4).min(frame_width);
+        let height = (lines.len() as i32 + 4).min(frame_height);
+        let y = top_left.y + (frame_height - height) / 2;
+        let x = top_left.x + (frame_width - width) / 2;
 
-        let hor_line = "─";
-        let vert_line = "│";
-        let corner_tl = "┌";
-        let corner_bl = "└";
-        let corner_tr = "┐";
-        let corner_br = "┘";
+        let mut overlay = Screen::new(ScreenSide::Full, 1.0);
+        overlay.bounds = (
+            Pair { y, x },
+            Pair {
+                y: y + height,
+                x: x + width,
+            },
+        );
+        for row in y..y + height {
+            mvaddstr(row, x, &" ".repeat(width.max(0) as usize));
+        }
+        let box_style = self.config.box_style;
+        box_style.on();
+        overlay.draw_box(
+            ScreenSide::Full,
+            1.0,
+            &Some(" confirm ".to_string()),
+        );
+        box_style.off();
 
-        // top line
-        self.pos.x = bounds.0.x;
-        self.pos.y = bounds.0.y;
-        self.addstr(corner_tl);
-        let label_len = match label {
-            Some(label) => {
-                self.addstr(&label);
-                label.len()
-            }
-            None => {
-                self.addstr(" preview ");
-                9
+        let inner = (width - 4).max(0) as usize;
+        for (row, line) in lines.iter().enumerate() {
+            let row = y + 1 + row as i32;
+            if row < y + height - 3 {
+                mvaddstr(row, x + 2, &truncate(line, inner));
             }
+        }
+        attron(A_BOLD());
+        mvaddstr(y + height - 2, x + 2, &truncate(question, inner));
+        attroff(A_BOLD());
+    }
+
+    fn raw_preview(&self) -> bool {
+        self.preview.as_ref().is_some_and(|prev| prev.raw)
+    }
+
+    /// Writes the hovered item's raw preview at the given position, if it
+    /// isn't there already. The whole screen is repainted first to get rid
+    /// of the previous one, so this must be called before curses refreshes
+    /// the screen, and `write_raw_preview` after.
+    fn place_raw_preview(&mut self, at: Option<(i32, i32)>) {
+        let shown = match (at, self.state.shown_preview) {
+            (Some((y, x)), Some(i)) => Some((i, y, x)),
+            _ => None,
         };
-        self.addstr(&hor_line.repeat(box_width - label_len - 2));
-        self.addstr(corner_tr);
+        if shown != self.state.raw_shown {
+            clearok(stdscr(), true);
+            self.state.raw_shown = shown;
+            self.state.raw_pending = shown.is_some();
+        }
+    }
 
-        // vertical lines
-        // accessing curses directly
-        for row in bounds.0.y + 1..bounds.1.y {
-            mvaddstr(row, bounds.0.x, vert_line);
-            mvaddstr(row, bounds.1.x - 1, vert_line);
+    /// Passes the raw preview placed by `place_raw_preview` on to the
+    /// terminal, bypassing curses.
+    fn write_raw_preview(&mut self) {
+        if !self.state.raw_pending {
+            return;
         }
+        self.state.raw_pending = false;
+        let (i, y, x) = match self.state.raw_shown {
+            Some(shown) => shown,
+            None => return,
+        };
+        if let Some(text) = &self.state.items[i].preview {
+            let raw: String = text.iter().map(|(s, _)| s.as_str()).collect();
+            let _ = write_raw(y, x, &raw);
+        }
+    }
 
-        // bottom line
-        self.pos.x = bounds.0.x;
-        self.pos.y = bounds.1.y - 1;
-        self.addstr(corner_bl);
-        self.addstr(&hor_line.to_string().repeat(box_width - 2));
-        self.addstr(corner_br);
+    /// The time left before the hovered item's preview is computed, if it is
+    /// being debounced.
+    fn preview_wait(&self) -> Option<Duration> {
+        let debounce = self.config.preview_debounce?;
+        let i = self.current()?;
+        if self.preview.is_none()
+            || self.state.items[i].preview.is_some()
+            || self.state.items[i].pending.is_some()
+            || self.manual_pending()
+        {
+            return None;
+        }
+        if self.state.hovered != Some(i) {
+            return Some(debounce);
+        }
+        debounce.checked_sub(self.state.hover_since.elapsed())
     }
 
-    fn get_key(&self) -> i32 {
-        getch()
+    /// Whether the item passes the filters and isn't in a collapsed section
+    /// or picked with `transfer`.
+    fn is_shown(&self, item: &Item) -> bool {
+        if self.config.transfer && item.chosen {
+            return false;
+        }
+        let mut parent = item.parent;
+        while let Some(i) = parent {
+            if self.state.items[i].collapsed {
+                return false;
+            }
+            parent = self.state.items[i].parent;
+        }
+        self.passes_filters(item)
     }
 
-    fn refresh(&mut self) {
-        refresh();
-        self.bounds = self
-            .side
-            .get_bounds((Pair { y: 0, x: 0 }, Self::get_size()), self.width);
+    /// Opens or closes the section headed by the item at position `i` in
+    /// `items`, and hovers it.
+    fn set_collapsed(&mut self, i: usize, collapsed: bool) -> RetCode {
+        if self.state.items[i].has_children {
+            self.state.items[i].collapsed = collapsed;
+        }
+        self.refilter(Some(i));
+        Pass
     }
 
-    fn erase(&mut self) {
-        erase();
-        self.bounds = self
-            .side
-            .get_bounds((Pair { y: 0, x: 0 }, Self::get_size()), self.width);
+    fn passes_filters(&self, item: &Item) -> bool {
+        self.filters
+            .iter()
+            .zip(&item.filter_hits)
+            .all(|(filter, &hit)| hit || !filter.active)
+            && self.matches_query(item)
     }
 
-    fn max_y(&mut self) -> usize {
-        self.bounds.1.y as usize
+    /// Whether the item contains the search query, ignoring case, or is
+    /// accepted by the `matcher`. Only the `search_column` field is searched
+    /// if one is set, and the text from `search_with` is searched instead of
+    /// the displayed one.
+    fn matches_query(&self, item: &Item) -> bool {
+        self.state.query.is_empty() || self.match_rank(item).is_some()
     }
 
-    fn _max_x(&mut self) -> usize {
-        self.bounds.1.x as usize
+    /// The part of the item's text that the search query is matched against.
+    fn searched_text<'i>(&self, item: &'i Item) -> Option<&'i str> {
+        match self.config.search_column {
+            Some(column) => item.search_text().split('\t').nth(column),
+            None => Some(item.search_text()),
+        }
     }
 
-    fn reset_pos(&mut self) {
-        self.pos.y = self.bounds.0.y;
-        self.pos.x = self.bounds.0.x;
-        self.items_on_screen = 0;
+    /// How well the item matches the query, lower being better: the byte
+    /// offset of the query in its text, or the negated score given by the
+    /// `matcher`. `None` if it doesn't match.
+    fn match_rank(&self, item: &Item) -> Option<i64> {
+        let text = self.searched_text(item)?;
+        match &self.matcher {
+            Some(matcher) => {
+                matcher(&self.state.query, text).map(|score| -(score as i64))
+            }
+            None => self
+                .fold(text)
+                .find(&self.fold(&self.state.query))
+                .map(|position| position as i64),
+        }
     }
 
-    fn get_size() -> Pair {
-        let mut size = Pair { y: 0, x: 0 };
-        getmaxyx(stdscr(), &mut size.y, &mut size.x);
-        size
+    /// Whether the visible items are ordered by how well they match the
+    /// query.
+    fn ranking(&self) -> bool {
+        (self.config.rank_matches || self.matcher.is_some())
+            && !self.state.query.is_empty()
     }
 
-    fn addstr(&mut self, s: &str) {
-        let screen_width = self.bounds.1.x - self.bounds.0.x;
-        let mut chars = s.chars();
-        let mut char_counter = 0;
-        let mut curr_string = String::new();
+    /// The key the visible items are sorted by. When ranking, better matches
+    /// come first, then shorter items.
+    fn rank_key(&self, i: usize) -> (i64, usize, usize) {
+        let item = &self.state.items[i];
+        if !self.ranking() {
+            return (0, 0, i);
+        }
+        let rank = self.match_rank(item).unwrap_or(i64::MAX);
+        (rank, item.search_text().chars().count(), i)
+    }
 
-        loop {
-            let next_char = chars.next();
-            if let Some(c) = next_char {
-                // TODO: shorten the code here
-                let mut both = false;
-                if char_counter >= screen_width {
-                    self.addstr_clean(&curr_string);
-                    curr_string.clear();
-                    self.pos.y += 1;
-                    self.pos.x = self.bounds.0.x;
-                    char_counter = 0;
-
-                    both = true;
-                }
-                if c == '\n' {
-                    self.addstr_clean(&curr_string);
-                    curr_string.clear();
-                    self.pos.y += 1;
-                    self.pos.x = self.bounds.0.x;
-                    char_counter = 0;
-                    both &= true;
-                    if both {
-                        self.pos.y -= 1;
-                    }
+    /// Normalizes text for comparison with the search query.
+    fn fold(&self, text: &str) -> String {
+        #[cfg(feature = "accent-folding")]
+        {
+            use unicode_normalization::char::is_combining_mark;
+            use unicode_normalization::UnicodeNormalization;
 
-                    continue;
-                }
-                if self.pos.y >= self.bounds.1.y {
-                    curr_string.clear();
-                    break;
-                }
-                assert!(c != '\n');
-                curr_string.push(c);
-                char_counter += 1;
-            } else {
-                break;
+            if self.config.fold_accents {
+                return text
+                    .nfd()
+                    .filter(|&c| !is_combining_mark(c))
+                    .collect::<String>()
+                    .to_lowercase();
             }
         }
-        assert!(!curr_string.contains('\n'));
-        self.addstr_clean(&curr_string);
+        text.to_lowercase()
     }
 
-    fn addstr_clean(&mut self, s: &str) {
-        mvaddstr(self.pos.y, self.pos.x, s);
-        self.pos.x += s.char_indices().count() as i32;
+    /// The position in `state.items` of the hovered item.
+    fn current(&self) -> Option<usize> {
+        self.state
+            .visible
+            .get(self.state.start + self.state.hover)
+            .copied()
     }
 
-    fn addch(&mut self, c: char) {
-        mvaddch(self.pos.y, self.pos.x, c as u32);
-        self.pos.x += 1;
+    /// Switches the filter on or off and recomputes the visible items.
+    fn toggle_filter(&mut self, filter: usize) -> RetCode {
+        self.filters[filter].active = !self.filters[filter].active;
+        self.apply_filters();
+        Pass
     }
 
-    fn skiplines(&mut self, n: i32) {
-        self.pos.y += n;
-        self.pos.x = self.bounds.0.x;
+    /// Recomputes the visible items, keeping the hovered item highlighted if
+    /// it is still visible. Otherwise the next visible item after it (or the
+    /// last one, if there is none) is hovered.
+    fn apply_filters(&mut self) {
+        self.refilter(self.current());
     }
 
-    fn set_pos(&mut self, new_side: ScreenSide, width: f64) {
-        assert!(width > 0.0 && width <= 1.0);
+    /// Like `apply_filters`, but keeps the item at position `prev` in `items`
+    /// hovered instead.
+    fn refilter(&mut self, prev: Option<usize>) {
+        if self.ranking() {
+            // Every item has to be scored before the best can be shown
+            while self.state.items.len() < RANK_LIMIT
+                && self.yield_item(self.state.items.len()).is_some()
+            {
+            }
+        }
+        let mut visible: Vec<usize> = (0..self.state.items.len())
+            .filter(|&i| self.is_shown(&self.state.items[i]))
+            .collect();
+        if self.ranking() {
+            visible.sort_by_cached_key(|&i| self.rank_key(i));
+            self.state.visible = visible;
+            // Hover the best match so that Enter picks it
+            let best = self.state.visible.first().copied();
+            self.pin_visible();
+            self.state.hover = 0;
+            self.hover_item(best);
+            return;
+        }
+        self.state.visible = visible;
 
-        self.side = new_side;
-        self.width = width;
+        // With nothing hovered before, e.g. when no item matched the query,
+        // start again from the top
+        let mut target = self.state.visible.first().copied();
+        if let Some(prev) = prev {
+            target = self.state.visible.iter().copied().find(|&i| i >= prev);
+            // Every item yielded from now on comes after the previous one
+            if target.is_none() && self.yield_visible(self.state.visible.len())
+            {
+                target = self.state.visible.last().copied();
+            }
+        }
+        self.pin_visible();
+        self.hover_item(target);
     }
-}
 
-struct Item<'a> {
-    icon: &'a str,
-    chosen_icon: &'a str,
-    chosen: bool,
-    repr: String,
-    preview: Option<String>,
-}
+    /// Hovers the item at the given position in `items`, or the last visible
+    /// item if it isn't visible. The item is placed at the `jump_anchor`, if
+    /// one is set.
+    fn hover_item(&mut self, target: Option<usize>) {
+        let row = target
+            .and_then(|target| {
+                self.state.visible.iter().position(|&i| i == target)
+            })
+            .or_else(|| self.state.visible.len().checked_sub(1))
+            .unwrap_or(0);
 
-impl<'a> Item<'a> {
-    fn new(
-        thing: &impl fmt::Display,
-        icon: &'a str,
-        chosen_icon: &'a str,
-    ) -> Item<'a> {
-        Item {
-            icon,
-            chosen_icon,
-            chosen: false,
-            repr: thing.to_string(),
-            preview: None,
+        // Otherwise keep the hovered row at the same height on the screen if
+        // possible
+        let rows = self.screen.items_on_screen;
+        let anchor = match self.config.jump_anchor {
+            Some(JumpAnchor::Top) => 0,
+            Some(JumpAnchor::OneThird) => rows / 3,
+            Some(JumpAnchor::Center) => rows / 2,
+            None => self.state.hover,
+        };
+        let hover = anchor.min(row);
+        self.state.start = row - hover;
+        self.state.hover = hover;
+    }
+
+    /// With `pin_selected`, moves the selected items to the top of the
+    /// visible items in the order they were selected. The recent items go
+    /// above them, in the order they were given. The other items keep their
+    /// order.
+    fn pin_visible(&mut self) {
+        let items = &self.state.items;
+        if self.config.pin_selected {
+            let (mut pinned, mut rest): (Vec<usize>, Vec<usize>) =
+                self.state.visible.iter().partition(|&&i| items[i].chosen());
+            let selection = &self.selection;
+            pinned.sort_by_key(|&i| {
+                selection.iter().position(|&index| index == items[i].index)
+            });
+            rest.sort_by_cached_key(|&i| self.rank_key(i));
+            pinned.extend(rest);
+            self.state.visible = pinned;
+        }
+        if !self.recent.is_empty() {
+            let recent = &self.recent;
+            let (mut pinned, rest): (Vec<usize>, Vec<usize>) = self
+                .state
+                .visible
+                .iter()
+                .partition(|&&i| recent.contains(&items[i].index));
+            pinned.sort_by_key(|&i| {
+                recent.iter().position(|&index| index == items[i].index)
+            });
+            pinned.extend(rest);
+            self.state.visible = pinned;
         }
     }
 
-    fn select(&mut self) {
-        self.chosen = !self.chosen;
+    /// The number of recent items at the top of the visible items.
+    fn recent_shown(&self) -> usize {
+        let items = &self.state.items;
+        self.state
+            .visible
+            .iter()
+            .take_while(|&&i| self.recent.contains(&items[i].index))
+            .count()
     }
 
-    fn chosen(&self) -> bool {
-        self.chosen
+    /// Drains the iterator and adds the remaining items in the order given by
+    /// the `sort_by` comparison.
+    fn yield_sorted(&mut self) {
+        let cmp = match self.sort_by.take() {
+            Some(cmp) => cmp,
+            None => return,
+        };
+        let offset = self.state.items.len();
+        let mut rest: Vec<(usize, D)> =
+            self.iter.by_ref().enumerate().collect();
+        // `sort_by` is stable, so equal items keep their original order
+        rest.sort_by(|a, b| cmp(&a.1, &b.1));
+        for (i, item) in rest {
+            self.push_item(offset + i, item);
+        }
+        // Kept for the items of `set_items`
+        self.sort_by = Some(cmp);
     }
 
-    fn icon(&self) -> &str {
-        if self.chosen {
-            self.chosen_icon
-        } else {
-            self.icon
+    /// Reserves the rows above and below the items used by the title, the
+    /// search prompt, the status bar and the help line, and the columns
+    /// used by the indent and the scrollbar.
+    fn reserve_space(&mut self) {
+        let reserved = self.reserved_rows(&Screen::get_size());
+        Self::set_margins(&self.config, &mut self.screen, reserved);
+        self.screen.layout();
+        if let Some(prev) = &mut self.preview {
+            prev.set_reserved(reserved);
+        }
+        if let Some(picked) = &mut self.picked {
+            picked.set_reserved(reserved);
+            picked.layout();
         }
     }
 
-    fn string(&self) -> &String {
-        &self.repr
+    /// The number of rows above and below the items used by other elements
+    /// in a terminal of the given size, or by the other pane of a
+    /// `DualMenu`.
+    fn reserved_rows(&self, size: &Pair) -> (i32, i32) {
+        let (top, bottom) = self.own_rows(size);
+        let (shared_top, shared_bottom) = self.state.shared_rows;
+        (top.max(shared_top), bottom.max(shared_bottom))
     }
 
-    fn preview<D: fmt::Display>(&mut self, thing: D, func: &DispFunc<D>) {
-        self.preview = Some(func.eval(thing));
+    /// The number of rows above and below the items used by this menu's
+    /// own title, prompt, status bar, footer and help line.
+    fn own_rows(&self, size: &Pair) -> (i32, i32) {
+        let top =
+            self.title_lines_in(size).len() as i32 + self.config.search as i32;
+        let bottom = self.config.help as i32
+            + self.status_bar() as i32
+            + self.footer_lines_in(size).len() as i32;
+        (top, bottom)
     }
-}
 
-impl<'a> fmt::Display for Item<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", format!("{} {}", self.icon(), self.repr))
+    fn set_margins(
+        config: &MenuSettings,
+        screen: &mut Screen,
+        reserved: (i32, i32),
+    ) {
+        screen.set_reserved(reserved);
+        screen.gutter = config.scrollbar as i32;
+        screen.indent = config.indent as i32;
+        screen.icon_gap = config.icon_gap as i32;
+        screen.rtl = config.rtl;
+        screen.max_item_width = config.max_item_width;
+        screen.highlight_fill = config.highlight_fill;
     }
-}
 
-struct Pair {
-    y: i32,
-    x: i32,
-}
+    /// Returns where the panes would be placed in a terminal with the given
+    /// number of rows and columns, without drawing anything. Each pane is
+    /// given as `(top, left, bottom, right)`, where the bottom row and right
+    /// column are just outside of it. Useful to check the layout in tests.
+    ///
+    /// ```
+    /// let menu = youchoose::Menu::new(0..100).preview(|n| n.to_string());
+    /// let layout = menu.layout(24, 80);
+    /// assert_eq!(layout.menu, (0, 0, 24, 40));
+    /// assert_eq!(layout.preview, Some((0, 41, 24, 80)));
+    /// ```
+    pub fn layout(&self, rows: i32, columns: i32) -> Layout {
+        let size = Pair {
+            y: rows,
+            x: columns,
+        };
+        let reserved = self.reserved_rows(&size);
+        let corners = |(top_left, bottom_right): (Pair, Pair)| {
+            (top_left.y, top_left.x, bottom_right.y, bottom_right.x)
+        };
 
-impl Pair {
-    fn clone(&self) -> Pair {
-        Pair {
-            y: self.y,
-            x: self.x,
+        let mut screen = Screen::new(self.screen.side, self.screen.width);
+        screen.set_frame(self.screen.frame);
+        Self::set_margins(&self.config, &mut screen, reserved);
+        let preview = self.preview.as_ref().map(|prev| {
+            let box_screen = &prev.box_screen;
+            let mut screen = Screen::new(box_screen.side, box_screen.width);
+            screen.set_frame(box_screen.frame);
+            screen.set_reserved(reserved);
+            corners(screen.bounds_in(&size))
+        });
+        Layout {
+            menu: corners(screen.bounds_in(&size)),
+            preview,
         }
     }
-}
 
-impl fmt::Display for Pair {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Pair({}, {})", self.y, self.x)
+    /// The title split on newlines and wrapped at the width of the frame.
+    fn title_lines(&self) -> Vec<String> {
+        self.title_lines_in(&Screen::get_size())
     }
-}
-
-/// Determines the side on which a pane should be located.
-#[derive(Copy, Clone)]
-pub enum ScreenSide {
-    Left,
-    Right,
-    Top,
-    Bottom,
-    /// This option is not affected by width. It will always fill the screen.
-    Full,
-}
+
+    fn title_lines_in(&self, size: &Pair) -> Vec<String> {
+        let mut title = self.title.clone();
+        if self.config.counter == Some(CounterPosition::Title) {
+            // Put the counter at the end of the first line
+            let counter = self.counter_text();
+            title = Some(match title {
+                Some(title) => match title.find('\n') {
+                    Some(end) => {
+                        format!(
+                            "{} {}{}",
+                            &title[..end],
+                            counter,
+                            &title[end..]
+                        )
+                    }
+                    None => format!("{} {}", title, counter),
+                },
+                None => counter,
+            });
+        }
+        let title = match &title {
+            Some(title) => title,
+            None => return Vec::new(),
+        };
+        let (top_left, bottom_right) = self.screen.frame_bounds_in(size);
+        wrap_lines(title, (bottom_right.x - top_left.x).max(1) as usize)
+    }
+
+    /// The lines of the footer, wrapped to the width of the menu's pane in a
+    /// terminal of the given size.
+    fn footer_lines_in(&self, size: &Pair) -> Vec<String> {
+        let footer = match &self.footer {
+            Some(footer) => footer,
+            None => return Vec::new(),
+        };
+        let (top_left, bottom_right) = self.screen.frame_bounds_in(size);
+        wrap_lines(footer, (bottom_right.x - top_left.x).max(1) as usize)
+    }
+
+    /// Draws the footer above the status bar and help line.
+    fn draw_footer(&mut self) {
+        let (top_left, bottom_right) = self.screen.frame_bounds();
+        let lines = self.footer_lines_in(&Screen::get_size());
+        let y = bottom_right.y
+            - self.config.help as i32
+            - self.status_bar() as i32
+            - lines.len() as i32;
+
+        attron(A_DIM());
+        for (row, line) in lines.iter().enumerate() {
+            mvaddstr(y + row as i32, top_left.x, line);
+        }
+        attroff(A_DIM());
+    }
+
+    fn draw_title(&mut self) {
+        let (top_left, bottom_right) = self.screen.frame_bounds();
+        let width = (bottom_right.x - top_left.x).max(0);
+
+        let style = self.config.title_style;
+        style.on();
+        for (row, line) in self.title_lines().iter().enumerate() {
+            let space = width - UnicodeWidthStr::width(line.as_str()) as i32;
+            let x = match self.config.title_align {
+                TextAlign::Left => 0,
+                TextAlign::Center => space / 2,
+                TextAlign::Right => space,
+            };
+            mvaddstr(top_left.y + row as i32, top_left.x + x.max(0), line);
+        }
+        style.off();
+    }
+
+    fn refresh(&mut self) {
+        self.reserve_space();
+        if let Some(prev) = &mut self.preview {
+            prev.layout();
+        }
+        if self.too_small() {
+            self.draw_too_small();
+            self.screen.refresh();
+            return;
+        }
+
+        if self.config.streaming {
+            self.yield_for(Duration::from_millis(SPINNER_INTERVAL_MS as u64));
+        } else {
+            // Maximum index that will fit on current screen state
+            let end = self.state.start + self.screen.max_y();
+            self.yield_visible(end);
+        }
+
+        let counter = match self.config.counter {
+            Some(CounterPosition::Preview) => Some(self.counter_text()),
+            _ => None,
+        };
+        let box_style = self.config.box_style;
+        box_style.on();
+        if self.screen.side == ScreenSide::Center {
+            self.draw_border();
+        }
+        self.screen.reset_pos();
+        if let Some(prev) = &mut self.preview {
+            prev.draw_box(counter.as_deref());
+            prev.screen.reset_pos();
+        }
+        box_style.off();
+        self.screen.icon_width = match self.shown_checkboxes() {
+            Some((unchecked, checked)) => {
+                let glyphs = UnicodeWidthStr::width(unchecked)
+                    .max(UnicodeWidthStr::width(checked))
+                    as i32;
+                if self.config.show_icon {
+                    self.pointer_width() + 1 + glyphs
+                } else {
+                    glyphs
+                }
+            }
+            None => self.pointer_width(),
+        };
+        // The pane may have shrunk, or the items grown, since the last move
+        self.fit_hover();
+        let hotkeys = self.hotkey_chars();
+        let recent = self.recent_shown();
+        let mut row = self.state.start;
+        self.state.row_spans.clear();
+        while row < self.state.visible.len() {
+            // Set the recent items apart from the rest
+            let header = match row {
+                0 if recent > 0 => Some("Recent".to_string()),
+                _ if row == recent && recent > 0 => {
+                    let width =
+                        self.screen.bounds.1.x - self.screen.bounds.0.x;
+                    Some("─".repeat(width.max(0) as usize))
+                }
+                _ => None,
+            };
+            if let Some(header) = header {
+                if !self.screen.write_header(&header) {
+                    break;
+                }
+            }
+            let hotkey = hotkeys.get(row - self.state.start).copied();
+            let first = self.screen.pos.y + 1;
+            if !self.write_row(row, hotkey) {
+                break;
+            }
+            let end = self.screen.pos.y + 1;
+            self.state.row_spans.push((row, first, end));
+            row += 1;
+        }
+
+        let mut raw_at = self.draw_preview();
+        self.draw_picked();
+
+        if self.config.streaming && !self.state.exhausted {
+            let frame = SPINNER[self.state.spinner % SPINNER.len()];
+            self.screen.write_loading(frame);
+        }
+
+        let no_match = self.state.visible.is_empty()
+            && self.state.exhausted
+            && self.filtered();
+        if let (true, Some(text)) = (no_match, &self.config.no_match_text) {
+            self.screen.write_centered(text);
+        }
+
+        if self.config.scrollbar {
+            self.draw_scrollbar();
+        }
+        if self.config.scroll_indicators {
+            self.draw_scroll_indicators();
+        }
+
+        if self.config.help {
+            self.draw_help();
+        }
+        if self.status_bar() {
+            self.draw_status();
+        }
+        if self.footer.is_some() {
+            self.draw_footer();
+        }
+        if self.config.search {
+            self.draw_prompt();
+        }
+        self.draw_title();
+        if self.state.quicklook {
+            raw_at = self.draw_quicklook();
+        }
+        if self.state.confirming.is_some() {
+            self.draw_confirm();
+        }
+        if self.raw_preview() {
+            self.place_raw_preview(raw_at);
+        }
+
+        self.screen.refresh();
+
+        if let Some(prev) = &mut self.preview {
+            prev.refresh();
+        }
+        self.write_raw_preview();
+    }
+
+    /// Writes the item on row `row` of the visible items on the next line of
+    /// the menu. Returns whether there was room for it.
+    fn write_row(&mut self, row: usize, hotkey: Option<char>) -> bool {
+        let item = &self.state.items[self.state.visible[row]];
+        let hovered = self.state.start + self.state.hover == row;
+        let checkboxes = self.shown_checkboxes();
+        let show_icon = self.config.show_icon;
+        let suffix = self.marks_suffix();
+        let base_icon = if suffix || checkboxes.is_some() {
+            item.icon
+        } else {
+            item.icon()
+        };
+        let icon = match self.hover_item_icon {
+            Some(icon) if hovered => match self.config.icon_priority {
+                IconPriority::Chosen if item.chosen() && !suffix => base_icon,
+                _ => icon,
+            },
+            _ => base_icon,
+        };
+        let marker = if suffix && item.chosen() {
+            Some(item.chosen_icon)
+        } else {
+            None
+        };
+        let pinned = self.config.pin_selected && item.chosen();
+        let bounds = &self.screen.bounds;
+        let width = (bounds.1.x - bounds.0.x).max(0) as usize;
+        let height = self.item_height(item, width);
+        let icon = match checkboxes {
+            Some((unchecked, checked)) => {
+                let glyph = if item.chosen() { checked } else { unchecked };
+                if show_icon {
+                    // Pad the icon so the boxes line up across rows
+                    let pad = self.pointer_width()
+                        - UnicodeWidthStr::width(icon) as i32;
+                    let pad = " ".repeat(pad.max(0) as usize);
+                    Some(format!("{}{} {}", icon, pad, glyph))
+                } else {
+                    Some(glyph.to_string())
+                }
+            }
+            None if show_icon => Some(icon.to_string()),
+            None => None,
+        };
+        if !self.screen.has_room(height as i32) {
+            return false;
+        }
+        self.screen.write_item(
+            item,
+            icon.as_deref(),
+            marker,
+            hovered && self.state.focused,
+            pinned,
+            hotkey,
+        );
+        true
+    }
+
+    /// Whether selection is shown by a marker at the end of the row.
+    fn marks_suffix(&self) -> bool {
+        // With a suffix marker or checkboxes, the leading icon doesn't show
+        // selection. Without icons, the marker is the only way to show it.
+        self.shown_checkboxes().is_none()
+            && (self.config.marker_position == MarkerPosition::Suffix
+                || !self.config.show_icon)
+    }
+
+    /// The glyphs of the unchecked and checked boxes, if they are shown.
+    fn shown_checkboxes(&self) -> Option<(&str, &str)> {
+        match &self.config.checkboxes {
+            Some((unchecked, checked)) if self.config.multiselect => {
+                Some((unchecked, checked))
+            }
+            _ => None,
+        }
+    }
+
+    /// The display width of the widest icon.
+    fn pointer_width(&self) -> i32 {
+        let icons = [self.item_icon, self.chosen_item_icon];
+        icons
+            .iter()
+            .chain(&self.hover_item_icon)
+            .map(|icon| UnicodeWidthStr::width(*icon) as i32)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The number of rows the item takes up in a pane `width` columns wide,
+    /// counting embedded newlines, wrapping and its description. Rows are
+    /// assumed to have a hotkey whenever hotkeys are on.
+    fn item_height(&self, item: &Item, width: usize) -> usize {
+        let desc_rows = item.desc.is_some() as usize;
+        if self.config.rtl {
+            // Right to left text is cut off instead of wrapped
+            return 1 + desc_rows;
+        }
+        let mut lead = UnicodeWidthStr::width(item.section_prefix().as_str());
+        if self.config.show_icon || self.shown_checkboxes().is_some() {
+            lead +=
+                self.screen.icon_width.max(0) as usize + self.config.icon_gap;
+        }
+        if self.config.hotkeys {
+            lead += 2;
+        }
+
+        let count = item.count_suffix();
+        let mut max = self.config.max_item_width;
+        if self.marks_suffix() && item.chosen() {
+            let room = width.saturating_sub(lead);
+            let room = marker_room(room, item.chosen_icon, &count);
+            max = Some(max.map_or(room, |max| max.min(room)));
+        }
+        let mut text = match max {
+            Some(max) => item
+                .string()
+                .split('\n')
+                .map(|line| truncate(line, max))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            None => item.string().clone(),
+        };
+        text.push_str(&count);
+        let mut rows = wrap_text(&text, lead, width).len();
+        if let Some(desc) = &item.desc {
+            rows += wrap_text(desc, lead, width).len();
+        }
+        rows
+    }
+
+    /// Scrolls down until the hovered item fits on the screen below the
+    /// items above it.
+    fn fit_hover(&mut self) {
+        let bounds = &self.screen.bounds;
+        let width = (bounds.1.x - bounds.0.x).max(0) as usize;
+        // A row is kept free above and below the items
+        let room = (bounds.1.y - bounds.0.y - 2).max(1) as usize;
+        let recent = self.recent_shown();
+        let row_height = |row: usize| {
+            let item = &self.state.items[self.state.visible[row]];
+            let header = recent > 0 && (row == 0 || row == recent);
+            self.item_height(item, width) + header as usize
+        };
+        let hovered = self.state.start + self.state.hover;
+        if hovered >= self.state.visible.len() {
+            return;
+        }
+        let heights: Vec<usize> =
+            (self.state.start..=hovered).map(row_height).collect();
+        let mut used: usize = heights.iter().sum();
+        for height in heights {
+            if used <= room || self.state.hover == 0 {
+                break;
+            }
+            used -= height;
+            self.state.start += 1;
+            self.state.hover -= 1;
+        }
+    }
+
+    /// Draws the hovered item's preview in the preview pane. Returns where a
+    /// raw preview should be written instead.
+    fn draw_preview(&mut self) -> Option<(i32, i32)> {
+        if self.manual_pending() {
+            let names: Vec<String> =
+                self.keys.preview.iter().map(|&c| key_name(c)).collect();
+            let hint = format!("{} to preview", names.join("/"));
+            if let Some(prev) = &mut self.preview {
+                prev.screen.write_centered(&hint);
+            }
+            return None;
+        }
+        let i = self.update_preview()?;
+        if let Some(placeholder) = self.preview_placeholder(i) {
+            if let Some(prev) = &mut self.preview {
+                prev.screen.write_centered(placeholder);
+            }
+            return None;
+        }
+        let text = self.state.items[i].preview.as_ref();
+        if let (Some(prev), Some(text)) = (&mut self.preview, text) {
+            if prev.raw {
+                let top_left = &prev.screen.bounds.0;
+                return Some((top_left.y, top_left.x));
+            }
+            prev.screen.addstr_styled(text);
+        }
+        None
+    }
+
+    /// With `minimal_redraw`, redraws only the rows of the previously and
+    /// newly hovered items and the preview after the hover moved from row
+    /// `from` without scrolling. Returns false if the whole menu has to be
+    /// redrawn instead.
+    fn redraw_hover(&mut self, from: usize) -> bool {
+        let to = self.state.start + self.state.hover;
+        let spans: Vec<(usize, i32, i32)> = self
+            .state
+            .row_spans
+            .iter()
+            .copied()
+            .filter(|&(row, _, _)| row == from || row == to)
+            .collect();
+        let found = spans.len() == if from == to { 1 } else { 2 };
+        if !found || self.state.quicklook || self.too_small() {
+            return false;
+        }
+
+        let hotkeys = self.hotkey_chars();
+        let shown = self.screen.items_on_screen;
+        for (row, first, end) in spans {
+            self.screen.blank(first, end);
+            self.screen.pos = Pair {
+                y: first - 1,
+                x: self.screen.bounds.0.x,
+            };
+            let hotkey = hotkeys.get(row - self.state.start).copied();
+            // The rows were drawn before, so they are known to fit
+            self.screen.items_on_screen = 0;
+            self.write_row(row, hotkey);
+        }
+        self.screen.items_on_screen = shown;
+
+        if let Some(prev) = &mut self.preview {
+            prev.screen
+                .blank(prev.screen.bounds.0.y, prev.screen.bounds.1.y);
+            prev.screen.reset_pos();
+        }
+        let raw_at = self.draw_preview();
+        if self.raw_preview() {
+            self.place_raw_preview(raw_at);
+        }
+
+        self.screen.refresh();
+        if let Some(prev) = &mut self.preview {
+            prev.refresh();
+        }
+        self.write_raw_preview();
+        true
+    }
+
+    /// Draws a box around the menu when it is centered.
+    fn draw_border(&mut self) {
+        let mut border = Screen::new(ScreenSide::Full, 1.0);
+        border.bounds = self.screen.side_bounds(self.screen.root_bounds());
+        border.draw_box(ScreenSide::Full, 1.0, &Some(String::new()));
+    }
+
+    /// Lists the items picked with `transfer` in a box beside the menu, in
+    /// the order they were picked.
+    fn draw_picked(&mut self) {
+        if self.picked.is_none() {
+            return;
+        }
+        let lines: Vec<String> = self
+            .selection
+            .iter()
+            .enumerate()
+            .filter_map(|(n, &index)| {
+                let repr = self.repr_of(index)?;
+                let line = repr.lines().next().unwrap_or("");
+                Some(format!("{}. {}", n + 1, line))
+            })
+            .collect();
+        let box_style = self.config.box_style;
+        let picked = match &mut self.picked {
+            Some(picked) => picked,
+            None => return,
+        };
+        box_style.on();
+        picked.draw_box(ScreenSide::Full, 1.0, &Some(" picked ".to_string()));
+        box_style.off();
+
+        let mut inner = Screen::new(ScreenSide::Full, 1.0);
+        let (top_left, bottom_right) = &picked.bounds;
+        inner.bounds = (
+            Pair {
+                y: top_left.y + 1,
+                x: top_left.x + 1,
+            },
+            Pair {
+                y: (bottom_right.y - 1).max(top_left.y + 1),
+                x: (bottom_right.x - 1).max(top_left.x + 1),
+            },
+        );
+        let width = (inner.bounds.1.x - inner.bounds.0.x) as usize;
+        let lines: Vec<String> =
+            lines.iter().map(|line| truncate(line, width)).collect();
+        inner.reset_pos();
+        inner.addstr(&lines.join("\n"));
+    }
+
+    /// Whether the panes are too small to draw anything useful in them.
+    fn too_small(&self) -> bool {
+        self.screen.too_small(MIN_HEIGHT, MIN_WIDTH)
+            || match &self.preview {
+                Some(prev) => prev.screen.too_small(1, 1),
+                None => false,
+            }
+    }
+
+    /// Replaces the menu with a message until the terminal is enlarged.
+    fn draw_too_small(&self) {
+        let (top_left, bottom_right) = self.screen.frame_bounds();
+        let width = (bottom_right.x - top_left.x).max(0) as usize;
+        let msg: String = "terminal too small".chars().take(width).collect();
+
+        erase();
+        mvaddstr(
+            top_left.y + (bottom_right.y - top_left.y) / 2,
+            top_left.x + ((width - msg.len()) / 2) as i32,
+            &msg,
+        );
+    }
+
+    /// Draws a one line summary of the keybindings of the active mode at the
+    /// bottom of the screen.
+    fn draw_help(&mut self) {
+        let mode = self.state.mode;
+        let mut help = match mode {
+            Mode::Normal => self.normal_hints(),
+            Mode::Search => vec![
+                format!("{} accept", key_name(10)),
+                format!("{} cancel search", key_name(27)),
+                format!("{} delete", key_name(KEY_BACKSPACE)),
+                format!("{}/{} move", key_name(KEY_UP), key_name(KEY_DOWN)),
+            ],
+            Mode::Custom(name) => vec![format!("-- {} --", name)],
+        };
+        // Keys bound to the mode itself are checked first, so list them too
+        help.extend(
+            self.mode_keys.iter().filter(|&&(m, _, _)| m == mode).map(
+                |&(_, key, action)| {
+                    format!("{} {}", key_name(key), action_name(action))
+                },
+            ),
+        );
+        match mode {
+            Mode::Normal => help.push("q quit".to_string()),
+            Mode::Search => (),
+            Mode::Custom(_) => help.push("esc back".to_string()),
+        }
+
+        let (top_left, bottom_right) = self.screen.frame_bounds();
+        let width = (bottom_right.x - top_left.x).max(0) as usize;
+        let line: String = help.join("  ").chars().take(width).collect();
+
+        attron(A_DIM());
+        mvaddstr(bottom_right.y - 1, top_left.x, &line);
+        attroff(A_DIM());
+    }
+
+    /// The hints for the keys in `Keys` that are active in normal mode.
+    fn normal_hints(&self) -> Vec<String> {
+        let keys = &self.keys;
+        let transfer = self.config.transfer;
+        let mut hints = vec![
+            (&keys.up, "up"),
+            (&keys.down, "down"),
+            (&keys.select, if transfer { "pick" } else { "select" }),
+        ];
+        if transfer {
+            hints.push((&keys.put_back, "put back"));
+        }
+        if self.config.multiselect {
+            hints.push((&keys.multiselect, "toggle"));
+            if !keys.clear_selection.is_empty() {
+                hints.push((&keys.clear_selection, "clear"));
+            }
+            if !keys.next_selected.is_empty() {
+                hints.push((&keys.next_selected, "next selected"));
+            }
+            if !keys.prev_selected.is_empty() {
+                hints.push((&keys.prev_selected, "previous selected"));
+            }
+        }
+        if !keys.clipboard.is_empty() {
+            hints.push((&keys.clipboard, "copy"));
+        }
+        if self.sections.is_some() {
+            hints.push((&keys.expand, "open"));
+            hints.push((&keys.collapse, "close"));
+        }
+        if !keys.accept_selection.is_empty() {
+            hints.push((&keys.accept_selection, "accept"));
+        }
+        if !keys.accept_current.is_empty() {
+            hints.push((&keys.accept_current, "accept current"));
+        }
+        if self.preview.is_some() && !keys.quicklook.is_empty() {
+            hints.push((&keys.quicklook, "look"));
+        }
+        if self.preview.as_ref().is_some_and(|prev| prev.manual) {
+            hints.push((&keys.preview, "preview"));
+        }
+        if self.config.quantities {
+            hints.push((&keys.increment, "more"));
+            hints.push((&keys.decrement, "fewer"));
+        }
+
+        let mut help: Vec<String> = hints
+            .iter()
+            .map(|(codes, action)| {
+                let mut names: Vec<String> =
+                    codes.iter().map(|&c| key_name(c)).collect();
+                // Enter and keypad Enter share a name
+                names.dedup();
+                format!("{} {}", names.join("/"), action)
+            })
+            .collect();
+        let instant = self.config.instant_select && !self.config.multiselect;
+        if self.config.hotkeys || instant {
+            help.push("1-9 pick".to_string());
+        }
+        if self.config.search {
+            let names: Vec<String> =
+                keys.search.iter().map(|&c| key_name(c)).collect();
+            help.push(format!("{} search", names.join("/")));
+        }
+        for filter in &self.filters {
+            let state = if filter.active { "on" } else { "off" };
+            help.push(format!("{} filter ({})", key_name(filter.key), state));
+        }
+        for &(key, _) in &self.action_keys {
+            help.push(format!("{} run", key_name(key)));
+        }
+        help
+    }
+
+    /// Whether a row is reserved for status messages, above the help line.
+    fn status_bar(&self) -> bool {
+        !self.keys.clipboard.is_empty()
+            || self.tick.is_some()
+            || self.config.counter == Some(CounterPosition::Status)
+    }
+
+    fn draw_status(&mut self) {
+        let (top_left, bottom_right) = self.screen.frame_bounds();
+        let width = (bottom_right.x - top_left.x).max(0) as usize;
+        let y = bottom_right.y - 1 - self.config.help as i32;
+
+        if self.config.counter == Some(CounterPosition::Status) {
+            let counter = self.counter_text();
+            let x = width.saturating_sub(counter.chars().count());
+            attron(A_DIM());
+            mvaddstr(y, top_left.x + x as i32, &counter);
+            attroff(A_DIM());
+        }
+
+        let status = match &self.state.status {
+            Some(status) => status,
+            None => return,
+        };
+        let line: String = status.chars().take(width).collect();
+        attron(A_BOLD());
+        mvaddstr(y, top_left.x, &line);
+        attroff(A_BOLD());
+    }
+
+    /// The number of items passing the filters out of the number of items,
+    /// e.g. `[12/340]`. Counts that aren't known yet are followed by `+`.
+    fn counter_text(&self) -> String {
+        let shown = match self.visible_total() {
+            Some(shown) => shown.to_string(),
+            None => format!("{}+", self.state.visible.len()),
+        };
+        let total = match self.total {
+            _ if self.state.exhausted => self.state.items.len().to_string(),
+            Some(total) => total.to_string(),
+            None => format!("{}+", self.state.items.len()),
+        };
+        format!("[{}/{}]", shown, total)
+    }
+
+    /// Runs the function of the action key at position `action` on the
+    /// hovered item's text, with curses suspended while it runs.
+    fn run_action(&mut self, action: usize) -> RetCode {
+        let text = match self.current() {
+            Some(i) => self.state.items[i].string().clone(),
+            None => return Pass,
+        };
+        def_prog_mode();
+        endwin();
+        (self.action_keys[action].1)(&text);
+        let _ = io::stdout().flush();
+        reset_prog_mode();
+        // Repaint the whole screen, whatever the function left on it
+        clear();
+        self.redraw();
+        Pass
+    }
+
+    /// Copies the hovered item's text and reports the result in the status
+    /// bar.
+    fn copy_item(&mut self) -> RetCode {
+        if let Some(i) = self.current() {
+            let text = self.state.items[i].string();
+            self.state.status = Some(match copy_to_clipboard(text) {
+                Ok(()) => format!("Copied {:?} to the clipboard", text),
+                Err(err) => {
+                    format!("Could not copy to the clipboard: {}", err)
+                }
+            });
+        }
+        Pass
+    }
+
+    /// Edits the query while searching. Enter keeps the query, ESC clears it.
+    fn handle_search_key(&mut self, val: i32) -> RetCode {
+        match val {
+            10 | KEY_ENTER => self.state.mode = Mode::Normal,
+            27 => {
+                self.state.mode = Mode::Normal;
+                self.state.query.clear();
+                self.apply_filters();
+            }
+            KEY_BACKSPACE | 127 | 8 => {
+                self.state.query.pop();
+                self.apply_filters();
+                return self.accept_single();
+            }
+            KEY_DOWN => return self.move_selection(1),
+            KEY_UP => return self.move_selection(-1),
+            32..=126 => {
+                self.state.query.push(val as u8 as char);
+                self.apply_filters();
+                return self.accept_single();
+            }
+            _ => {}
+        }
+        Pass
+    }
+
+    /// Returns whether the initial query already singles out an item, and
+    /// accepts it if so.
+    fn accept_initial(&mut self) -> bool {
+        // The items loaded to check the query are not sorted again later
+        self.yield_sorted();
+        !self.config.confirm && matches!(self.accept_single(), Done)
+    }
+
+    /// With `auto_accept_single`, returns the only item matching the query.
+    fn accept_single(&mut self) -> RetCode {
+        if !self.config.auto_accept_single || self.state.query.is_empty() {
+            return Pass;
+        }
+        if self.yield_visible(0) && !self.yield_visible(1) {
+            // Accepted like the select key would, along with any toggled items
+            let item = &mut self.state.items[self.state.visible[0]];
+            if !item.chosen() {
+                let index = item.index;
+                item.select();
+                self.selection.push(index);
+                self.record_toggles(vec![(index, true)]);
+            }
+            return Done;
+        }
+        Pass
+    }
+
+    /// Draws the search query on the row reserved below the title.
+    fn draw_prompt(&mut self) {
+        let (top_left, bottom_right) = self.screen.frame_bounds();
+        let y = top_left.y + self.title_lines().len() as i32;
+        let width = (bottom_right.x - top_left.x).max(0) as usize;
+        let query = &self.state.query;
+        let (line, attr) = if self.state.mode == Mode::Search {
+            (format!("/{}_", query), A_BOLD())
+        } else if query.is_empty() {
+            let names: Vec<String> =
+                self.keys.search.iter().map(|&c| key_name(c)).collect();
+            (format!("{} to search", names.join("/")), A_DIM())
+        } else {
+            (format!("/{}", query), A_NORMAL())
+        };
+        let line: String = line.chars().take(width).collect();
+
+        attron(attr);
+        mvaddstr(y, top_left.x, &line);
+        attroff(attr);
+    }
+
+    /// Routes a key to the action bound to it in the active mode.
+    fn process_key(&mut self, val: i32) -> RetCode {
+        let mode = self.state.mode;
+        let bound = self
+            .mode_keys
+            .iter()
+            .find(|&&(m, key, _)| m == mode && key == val)
+            .map(|&(_, _, action)| action);
+        if self.state.quicklook {
+            // The overlay is modal, only its own key closes it
+            if bound == Some(Action::QuickLook)
+                || self.keys.quicklook.contains(&val)
+            {
+                self.state.quicklook = false;
+            }
+            return Pass;
+        }
+        if let Some(action) = bound {
+            return self.perform(action);
+        }
+
+        match mode {
+            Mode::Normal => self.process_normal_key(val),
+            Mode::Search => self.handle_search_key(val),
+            Mode::Custom(_) => {
+                if val == 27 {
+                    self.state.mode = Mode::Normal;
+                }
+                Pass
+            }
+        }
+    }
+
+    fn perform(&mut self, action: Action) -> RetCode {
+        let multiselect = self.config.multiselect;
+        let quantities = multiselect && self.config.quantities;
+        match action {
+            Action::Down => self.move_hover(1),
+            Action::Up => self.move_hover(-1),
+            Action::Select => match self.current() {
+                Some(i) if self.state.items[i].has_children => {
+                    let collapsed = self.state.items[i].collapsed;
+                    self.set_collapsed(i, !collapsed)
+                }
+                _ if self.config.transfer => self.pick_item(),
+                _ => self.select_item(),
+            },
+            Action::Expand => match self.current() {
+                Some(i) => self.set_collapsed(i, false),
+                None => Pass,
+            },
+            Action::Collapse => match self.current() {
+                // Collapse the section the hovered item is in, unless it is
+                // an open section itself
+                Some(i) => {
+                    let item = &self.state.items[i];
+                    match item.parent {
+                        Some(parent)
+                            if !item.has_children || item.collapsed =>
+                        {
+                            self.set_collapsed(parent, true)
+                        }
+                        _ => self.set_collapsed(i, true),
+                    }
+                }
+                None => Pass,
+            },
+            Action::Multiselect if multiselect => self.multiselect_item(),
+            Action::ClearSelection if multiselect => self.clear_selection(),
+            Action::Undo if multiselect => self.undo_toggle(),
+            Action::Redo if multiselect => self.redo_toggle(),
+            Action::NextSelected if multiselect => self.jump_selected(1),
+            Action::PrevSelected if multiselect => self.jump_selected(-1),
+            Action::Multiselect
+            | Action::ClearSelection
+            | Action::Undo
+            | Action::Redo
+            | Action::NextSelected
+            | Action::PrevSelected => Pass,
+            Action::Increment if quantities => self.change_count(1),
+            Action::Decrement if quantities => self.change_count(-1),
+            Action::Increment | Action::Decrement => Pass,
+            Action::PutBack if self.config.transfer => self.put_back(),
+            Action::PutBack => Pass,
+            Action::Preview => {
+                if let Some(i) = self.current() {
+                    self.load_preview(i);
+                }
+                Pass
+            }
+            Action::AcceptSelection | Action::Quit => Done,
+            Action::AcceptCurrent => self.accept_current(),
+            Action::Copy => self.copy_item(),
+            Action::QuickLook => {
+                self.state.quicklook = !self.state.quicklook;
+                Pass
+            }
+            Action::SetMode(mode) => {
+                self.state.mode = mode;
+                Pass
+            }
+        }
+    }
+
+    fn process_normal_key(&mut self, val: i32) -> RetCode {
+        if self.config.search && self.keys.search.contains(&val) {
+            return self.perform(Action::SetMode(Mode::Search));
+        }
+
+        if let Some(row) =
+            self.hotkey_chars().iter().position(|&c| c as i32 == val)
+        {
+            if row < self.screen.items_on_screen {
+                self.state.hover = row;
+                return if self.config.transfer {
+                    self.pick_item()
+                } else {
+                    self.select_item()
+                };
+            }
+        }
+
+        // After hotkeys, which pick the same rows with the same digits
+        let digit = ('1' as i32..='9' as i32).contains(&val);
+        if self.config.instant_select
+            && !self.config.multiselect
+            && digit
+            && self.key_is_free(val)
+        {
+            let row = (val - '1' as i32) as usize;
+            if row < self.screen.items_on_screen {
+                self.state.hover = row;
+                return self.select_item();
+            }
+        }
+
+        if let Some(filter) = self.filters.iter().position(|f| f.key == val) {
+            return self.toggle_filter(filter);
+        }
+
+        if let Some(action) =
+            self.action_keys.iter().position(|&(key, _)| key == val)
+        {
+            return self.run_action(action);
+        }
+
+        let manual = self.preview.as_ref().is_some_and(|prev| prev.manual);
+        let keys = &self.keys;
+        let actions = [
+            (&keys.down, Action::Down),
+            (&keys.up, Action::Up),
+            (&keys.multiselect, Action::Multiselect),
+            (&keys.clear_selection, Action::ClearSelection),
+            (&keys.select, Action::Select),
+            (&keys.clipboard, Action::Copy),
+            (&keys.accept_selection, Action::AcceptSelection),
+            (&keys.accept_current, Action::AcceptCurrent),
+            (&keys.expand, Action::Expand),
+            (&keys.collapse, Action::Collapse),
+            (&keys.undo, Action::Undo),
+            (&keys.redo, Action::Redo),
+            (&keys.quicklook, Action::QuickLook),
+            (&keys.increment, Action::Increment),
+            (&keys.decrement, Action::Decrement),
+            (&keys.put_back, Action::PutBack),
+            (&keys.next_selected, Action::NextSelected),
+            (&keys.prev_selected, Action::PrevSelected),
+            (&keys.preview, Action::Preview),
+        ];
+        let action = actions
+            .iter()
+            .filter(|(_, action)| {
+                self.config.multiselect
+                    || !matches!(
+                        action,
+                        Action::Multiselect
+                            | Action::ClearSelection
+                            | Action::Undo
+                            | Action::Redo
+                            | Action::NextSelected
+                            | Action::PrevSelected
+                    )
+            })
+            .filter(|(_, action)| {
+                self.config.quantities
+                    || !matches!(action, Action::Increment | Action::Decrement)
+            })
+            .filter(|(_, action)| {
+                self.config.transfer || !matches!(action, Action::PutBack)
+            })
+            .filter(|(_, action)| manual || !matches!(action, Action::Preview))
+            .find(|(codes, _)| codes.contains(&val))
+            .map(|&(_, action)| action);
+        match action {
+            Some(action) => self.perform(action),
+            None => Pass,
+        }
+    }
+
+    /// The keys used to pick the visible items directly, in screen order.
+    fn hotkey_chars(&self) -> Vec<char> {
+        if !self.config.hotkeys {
+            return Vec::new();
+        }
+        ('1'..='9')
+            .chain('a'..='z')
+            .filter(|&c| self.key_is_free(c as i32))
+            .collect()
+    }
+
+    /// Whether the key isn't bound to anything in normal mode.
+    fn key_is_free(&self, c: i32) -> bool {
+        c != 'q' as i32
+            && !self.keys.all().any(|key| key == c)
+            && !self
+                .mode_keys
+                .iter()
+                .any(|&(mode, key, _)| mode == Mode::Normal && key == c)
+            && !self.filters.iter().any(|f| f.key == c)
+            && !self.action_keys.iter().any(|&(key, _)| key == c)
+    }
+
+    fn clear_selection(&mut self) -> RetCode {
+        let toggles = mem::take(&mut self.selection)
+            .into_iter()
+            .map(|index| (index, false))
+            .collect();
+        self.record_toggles(toggles);
+        for item in &mut self.state.items {
+            item.chosen = false;
+        }
+        if self.config.pin_selected {
+            self.apply_filters();
+        }
+        Pass
+    }
+
+    /// Replaces the selection with the hovered item, deselecting the other
+    /// toggled items in one undoable step.
+    fn accept_current(&mut self) -> RetCode {
+        let i = match self.current() {
+            Some(i) => i,
+            None => return Pass,
+        };
+        let index = self.state.items[i].index;
+        let mut toggles: Vec<(usize, bool)> = self
+            .selection
+            .iter()
+            .filter(|&&chosen| chosen != index)
+            .map(|&chosen| (chosen, false))
+            .collect();
+        if !self.state.items[i].chosen {
+            toggles.push((index, true));
+        }
+        for &(index, chosen) in &toggles {
+            self.set_chosen(index, chosen);
+        }
+        if !toggles.is_empty() {
+            self.push_undo(toggles);
+        }
+        Done
+    }
+
+    fn select_item(&mut self) -> RetCode {
+        // Accept the toggled items as they are, unless there are none
+        if self.config.multiselect
+            && self.config.enter_selects_hover
+            && !self.selection.is_empty()
+        {
+            return Done;
+        }
+
+        if let Some(i) = self.current() {
+            if !self.enter_submenu(self.state.items[i].index) {
+                return Pass;
+            }
+        }
+        let curr_item = match self.current() {
+            Some(i) => &mut self.state.items[i],
+            None => return Pass,
+        };
+        let curr_item_idx = curr_item.index;
+        // This replaces a special case that accepted the selection only when
+        // `selection.last() == Some(curr)`, and added any other selected item
+        // to it again. Now a selected item is accepted along with the rest,
+        // unless `select_key_toggles_last` is set and it was selected last,
+        // in which case it is taken off the end of the selection.
+        if curr_item.chosen() {
+            let last = self.selection.last() == Some(&curr_item_idx);
+            if !(self.config.select_key_toggles_last && last) {
+                return Done;
+            }
+            self.set_chosen(curr_item_idx, false);
+            self.push_undo(vec![(curr_item_idx, false)]);
+            self.after_toggles();
+            return Pass;
+        }
+        curr_item.select();
+        self.selection.push(curr_item_idx);
+        self.record_toggles(vec![(curr_item_idx, true)]);
+        Done
+    }
+
+    /// Moves the hovered item out of the list, to the end of the selection.
+    /// The menu ends once every item has been picked.
+    fn pick_item(&mut self) -> RetCode {
+        let i = match self.current() {
+            Some(i) => i,
+            None => return Pass,
+        };
+        let index = self.state.items[i].index;
+        self.state.items[i].chosen = true;
+        self.selection.push(index);
+        self.record_toggles(vec![(index, true)]);
+        self.refilter(Some(i));
+        if !self.filtered() && !self.yield_visible(0) {
+            return Done;
+        }
+        Pass
+    }
+
+    /// Puts the last picked item back in the list and hovers it.
+    fn put_back(&mut self) -> RetCode {
+        let index = match self.selection.pop() {
+            Some(index) => index,
+            None => return Pass,
+        };
+        let pos = self.state.items.iter().position(|item| item.index == index);
+        if let Some(i) = pos {
+            self.state.items[i].chosen = false;
+        }
+        self.record_toggles(vec![(index, false)]);
+        self.refilter(pos);
+        Pass
+    }
+
+    /// Shows the submenu of the item with the original index `index`, if it
+    /// has one. Returns whether the item should be selected: it has no
+    /// submenu, or something was chosen in it.
+    fn enter_submenu(&mut self, index: usize) -> bool {
+        let open = match self.submenus.iter().find(|(i, _)| *i == index) {
+            Some((_, open)) => open,
+            None => return true,
+        };
+        match open() {
+            Ok(path) if !path.is_empty() => {
+                self.path = Some(index).into_iter().chain(path).collect();
+                true
+            }
+            Ok(_) => false,
+            Err(err) => {
+                self.state.status =
+                    Some(format!("Could not open the submenu: {}", err));
+                false
+            }
+        }
+    }
+
+    /// The original indices chosen on the way to the final choice: the item
+    /// chosen in this menu, then the one chosen in its submenu and so on.
+    /// The innermost menu contributes its first chosen index. Empty if
+    /// nothing was chosen.
+    pub fn path(&self) -> Vec<usize> {
+        if self.path.is_empty() {
+            self.selection().into_iter().take(1).collect()
+        } else {
+            self.path.clone()
+        }
+    }
+
+    /// Adds `delta` to the number of times the hovered item is chosen. The
+    /// item is selected when its count rises above zero, and deselected when
+    /// it drops back to zero.
+    fn change_count(&mut self, delta: i32) -> RetCode {
+        let item = match self.current() {
+            Some(i) => &mut self.state.items[i],
+            None => return Pass,
+        };
+        let old = if item.chosen { item.count as i32 } else { 0 };
+        let count = old + delta;
+        if count < 0 {
+            return Pass;
+        }
+        item.count = count.max(1) as u32;
+        let (index, chosen) = (item.index, count > 0);
+        if chosen != item.chosen {
+            self.set_chosen(index, chosen);
+            self.push_undo(vec![(index, chosen)]);
+            self.after_toggles();
+        }
+        Pass
+    }
+
+    /// Returns each chosen index with the number of times it was chosen, in
+    /// the order of `selection`. Without `quantities`, every count is 1.
+    pub fn counts(&self) -> Vec<(usize, u32)> {
+        self.selection()
+            .into_iter()
+            .map(|index| {
+                let count = self
+                    .state
+                    .items
+                    .iter()
+                    .find(|item| item.index == index && item.chosen)
+                    .map_or(1, |item| item.count);
+                (index, count)
+            })
+            .collect()
+    }
+
+    /// Hovers the closest selected item after the hovered one, or before it
+    /// if `direction` is negative, wrapping around the loaded items.
+    fn jump_selected(&mut self, direction: i32) -> RetCode {
+        let visible = &self.state.visible;
+        let rows = visible.len();
+        let hover = self.state.start + self.state.hover;
+        let target = (1..=rows)
+            .map(|step| {
+                let step = if direction < 0 { rows - step } else { step };
+                visible[(hover + step) % rows]
+            })
+            .find(|&i| self.state.items[i].chosen);
+        if target.is_some() {
+            self.hover_item(target);
+        }
+        Pass
+    }
+
+    /// Logs a group of toggles that is undone in one step.
+    fn record_toggles(&mut self, toggles: Vec<(usize, bool)>) {
+        if toggles.is_empty() {
+            return;
+        }
+        if self.config.record_history {
+            self.history.extend_from_slice(&toggles);
+        }
+        self.push_undo(toggles);
+    }
+
+    /// Lets a group of toggles made with `set_chosen`, which logs them in
+    /// the history itself, be undone in one step.
+    fn push_undo(&mut self, toggles: Vec<(usize, bool)>) {
+        self.undo.push(toggles);
+        self.redo.clear();
+    }
+
+    /// Reverts the last group of toggles and moves it to the redo stack.
+    fn undo_toggle(&mut self) -> RetCode {
+        if let Some(toggles) = self.undo.pop() {
+            for &(index, chosen) in toggles.iter().rev() {
+                self.set_chosen(index, !chosen);
+            }
+            self.redo.push(toggles);
+            self.after_toggles();
+        }
+        Pass
+    }
+
+    /// Applies the last undone group of toggles again.
+    fn redo_toggle(&mut self) -> RetCode {
+        if let Some(toggles) = self.redo.pop() {
+            for &(index, chosen) in &toggles {
+                self.set_chosen(index, chosen);
+            }
+            self.undo.push(toggles);
+            self.after_toggles();
+        }
+        Pass
+    }
+
+    /// Marks the item with the original index `index` as chosen or not,
+    /// keeping the selection in sync and loading items up to it first.
+    /// Returns false if there is no such item.
+    fn set_chosen(&mut self, index: usize, chosen: bool) -> bool {
+        if self.yield_item(index).is_none() {
+            return false;
+        }
+        // With `sort_by`, the items are not stored in their original order
+        let item =
+            self.state.items.iter_mut().find(|item| item.index == index);
+        match item {
+            Some(item) => item.chosen = chosen,
+            None => return false,
+        }
+        let pos = self.selection.iter().position(|&i| i == index);
+        match pos {
+            Some(pos) if !chosen => {
+                self.selection.remove(pos);
+            }
+            None if chosen => self.selection.push(index),
+            _ => (),
+        }
+        if self.config.record_history {
+            self.history.push((index, chosen));
+        }
+        true
+    }
+
+    fn after_toggles(&mut self) {
+        if self.config.pin_selected || self.config.transfer {
+            self.apply_filters();
+        }
+    }
+
+    /// Returns every time an item was selected or deselected, as its
+    /// original index and whether it was selected, oldest first. Only
+    /// recorded with `record_history`.
+    pub fn history(&self) -> &[(usize, bool)] {
+        &self.history
+    }
+
+    /// Hovers the item with the original index `index`, scrolling as little
+    /// as needed to show it, and redraws the menu. Does nothing if the item
+    /// is filtered out. Meant to be called between the events of a shown
+    /// menu, e.g. to script a demo.
+    ///
+    /// ```no_run
+    /// use youchoose::MenuEvent;
+    ///
+    /// let mut menu = youchoose::Menu::new(0..100);
+    /// let mut events = menu.events().unwrap();
+    /// // Jump to the answer whenever an unbound key is pressed
+    /// while let Some(event) = events.next() {
+    ///     if let MenuEvent::Key(_) = event {
+    ///         events.menu().set_hover(42);
+    ///     }
+    /// }
+    /// ```
+    pub fn set_hover(&mut self, index: usize) {
+        if let Some(row) = self.visible_row(index) {
+            self.hover_item(Some(self.state.visible[row]));
+            self.redraw_shown();
+        }
+    }
+
+    /// Selects the item with the original index `index`, or deselects it
+    /// if it is selected, and redraws the menu. The toggle can be undone
+    /// like one made with a key. Only works in multiselect mode, and does
+    /// nothing if the iterator has no item at `index`.
+    pub fn toggle(&mut self, index: usize) {
+        if !self.config.multiselect {
+            return;
+        }
+        let chosen = !self.selection.contains(&index);
+        if !self.set_chosen(index, chosen) {
+            return;
+        }
+        self.push_undo(vec![(index, chosen)]);
+        self.after_toggles();
+        self.redraw_shown();
+    }
+
+    /// Scrolls the list so that the item with the original index `index` is
+    /// at the top, or as close to it as the end of the list allows, and
+    /// redraws the menu. The hover stays on the same row of the screen.
+    /// Does nothing if the item is filtered out.
+    pub fn scroll_to(&mut self, index: usize) {
+        if let Some(row) = self.visible_row(index) {
+            let rows = self.screen.items_on_screen.max(1);
+            self.yield_visible(row + rows);
+            let last = self.state.visible.len() - 1;
+            self.state.start = row.min((last + 1).saturating_sub(rows));
+            self.state.hover = self.state.hover.min(last - self.state.start);
+            self.redraw_shown();
+        }
+    }
+
+    /// The original index of the item drawn on the terminal row `y`, as of
+    /// the last redraw. Every line of an item counts, including its
+    /// description. Returns `None` for rows without an item, such as the
+    /// title and prompt, or if the menu hasn't been drawn.
+    ///
+    /// ```no_run
+    /// use youchoose::MenuEvent;
+    ///
+    /// let mut menu = youchoose::Menu::new(0..100);
+    /// let mut events = menu.events().unwrap();
+    /// while let Some(event) = events.next() {
+    ///     // Jump to whatever is drawn on the fifth row when `h` is pressed
+    ///     if event == MenuEvent::Key('h' as i32) {
+    ///         if let Some(index) = events.menu().item_at_row(4) {
+    ///             events.menu().set_hover(index);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn item_at_row(&self, y: i32) -> Option<usize> {
+        self.state
+            .row_spans
+            .iter()
+            .find(|&&(_, first, end)| first <= y && y < end)
+            .and_then(|&(row, _, _)| self.state.visible.get(row))
+            .map(|&i| self.state.items[i].index)
+    }
+
+    /// The row in `visible` of the item with the original index `index`,
+    /// loading items until it is found.
+    fn visible_row(&mut self, index: usize) -> Option<usize> {
+        loop {
+            let items = &self.state.items;
+            if let Some(row) = self
+                .state
+                .visible
+                .iter()
+                .position(|&i| items[i].index == index)
+            {
+                return Some(row);
+            }
+            self.yield_item(self.state.items.len())?;
+        }
+    }
+
+    /// Redraws the menu if it is on the screen.
+    fn redraw_shown(&mut self) {
+        if self.state.prepared {
+            self.redraw();
+        }
+    }
+
+    fn multiselect_item(&mut self) -> RetCode {
+        let curr_item = match self.current() {
+            Some(i) => &mut self.state.items[i],
+            None => return Pass,
+        };
+        let curr_item_idx = curr_item.index;
+        curr_item.select();
+
+        let item_idx_pos =
+            match self.selection.iter().position(|x| *x == curr_item_idx) {
+                Some(idx) => idx as i32,
+                None => -1,
+            };
+
+        let chosen = curr_item.chosen();
+        if chosen && item_idx_pos == -1 {
+            self.selection.push(curr_item_idx);
+        } else if !chosen && item_idx_pos != -1 {
+            self.selection.remove(item_idx_pos as usize);
+        }
+        self.record_toggles(vec![(curr_item_idx, chosen)]);
+
+        if self.config.pin_selected {
+            // A newly pinned item moves up, so move on to the one after it.
+            // An unpinned item is followed back to its place in the list.
+            let pos = self.state.start + self.state.hover;
+            let next = if chosen { pos + 1 } else { pos };
+            let target = self
+                .state
+                .visible
+                .get(next)
+                .or_else(|| self.state.visible.get(pos))
+                .copied();
+            self.pin_visible();
+            let start = self.state.start;
+            let row = target.and_then(|target| {
+                self.state.visible.iter().position(|&i| i == target)
+            });
+            match row {
+                // Leave the list in place if the item is still on the screen
+                Some(row)
+                    if row >= start
+                        && row < start + self.screen.items_on_screen =>
+                {
+                    self.state.hover = row - start;
+                }
+                _ => self.hover_item(target),
+            }
+        }
+
+        Pass
+    }
+
+    fn scroll(&mut self, amount: i32) {
+        self.state.start = ((self.state.start as i32) + amount) as usize;
+        assert!(self.state.start < 1_000_000);
+    }
+
+    /// Moves the hover in `direction`, noting where it came from if nothing
+    /// else on the screen changed.
+    fn move_hover(&mut self, direction: i32) -> RetCode {
+        let start = self.state.start;
+        let row = start + self.state.hover;
+        let loaded = self.state.visible.len();
+        let ret = self.move_accelerated(direction);
+        if self.state.start == start && self.state.visible.len() == loaded {
+            self.state.moved_from = Some(row);
+        }
+        ret
+    }
+
+    /// Moves the hover one row in `direction`, or more with key acceleration
+    /// when the key is held down.
+    fn move_accelerated(&mut self, direction: i32) -> RetCode {
+        let mut rows = 1;
+        if self.config.key_acceleration {
+            let now = Instant::now();
+            let quick = match self.state.last_move {
+                Some((last, time)) => {
+                    last == direction
+                        && now - time
+                            < Duration::from_millis(ACCEL_INTERVAL_MS)
+                }
+                None => false,
+            };
+            self.state.streak = if quick { self.state.streak + 1 } else { 0 };
+            self.state.last_move = Some((direction, now));
+            rows = (1 + self.state.streak / ACCEL_PRESSES_PER_ROW)
+                .min(ACCEL_MAX_ROWS);
+        }
+        if direction > 0 {
+            // Load the items moved past, which aren't on the screen yet
+            let end = self.state.start + self.screen.items_on_screen;
+            self.yield_visible(end + rows as usize);
+        }
+        for _ in 0..rows {
+            self.move_selection(direction);
+        }
+        Pass
+    }
+
+    /// The rows of the screen the hovered item is kept between when moving
+    /// it, by scrolling the list.
+    fn scroll_band(&self) -> (f64, f64) {
+        let rows = self.screen.items_on_screen as f64;
+        match self.config.jump_anchor {
+            // Scroll a row before the edge, so the next item is in view
+            Some(JumpAnchor::Top) => (1.0, rows - 2.0),
+            Some(JumpAnchor::Center) => (rows * 0.5, rows * 0.5),
+            Some(JumpAnchor::OneThird) | None => (rows * 0.33, rows * 0.67),
+        }
+    }
+
+    fn move_selection(&mut self, amount: i32) -> RetCode {
+        if self.config.scroll_mode == ScrollMode::Page {
+            return self.move_paged(amount);
+        }
+        let num_items = self.screen.items_on_screen as f64;
+        let new_hover = ((self.state.hover as i32) + amount) as f64;
+
+        // Taller items can leave the hover on the last row shown, so moving
+        // past it only stops at the end of the items
+        let at_end = self.state.start + self.screen.items_on_screen
+            >= self.state.visible.len();
+        if new_hover < 0.0 || (new_hover >= num_items && at_end) {
+            return Pass;
+        }
+
+        self.state.hover = new_hover as usize;
+
+        let (low, high) = self.scroll_band();
+        if new_hover > high
+            && self.state.start + self.screen.items_on_screen
+                < self.state.visible.len()
+        {
+            self.scroll(1);
+            self.state.hover -= 1;
+        } else if new_hover < low && self.state.start > 0 && amount < 0 {
+            self.scroll(-1);
+            self.state.hover += 1;
+        }
+        self.fit_hover();
+
+        Pass
+    }
+
+    /// Moves the hover within the screen, turning to the next or previous
+    /// screen of items when it moves past the edge.
+    fn move_paged(&mut self, amount: i32) -> RetCode {
+        let rows = self.screen.items_on_screen;
+        let new_hover = self.state.hover as i32 + amount;
+        let start = self.state.start;
+        if new_hover < 0 {
+            if start > 0 {
+                // Land on the item just before the old page
+                self.state.start = start.saturating_sub(rows.max(1));
+                self.state.hover = start - self.state.start - 1;
+            }
+        } else if new_hover as usize >= rows {
+            if start + rows < self.state.visible.len() {
+                self.state.start = start + rows;
+                self.state.hover = 0;
+            }
+        } else {
+            self.state.hover = new_hover as usize;
+        }
+        Pass
+    }
+
+    /// Add a preview pane that displays the result of applying the function
+    /// passed in to each item in the iterable. The function must return a
+    /// String.
+    pub fn preview<F>(self, func: F) -> Menu<'a, I, D>
+    where
+        F: Fn(D) -> String + 'static,
+    {
+        self.preview_styled(move |item| {
+            vec![(func(item), ItemStyle::default())]
+        })
+    }
+
+    /// Like [`preview`](struct.Menu.html#method.preview), but places the
+    /// preview pane as [`preview_pos`](struct.Menu.html#method.preview_pos)
+    /// would, instead of on the right half of the screen.
+    ///
+    /// ```no_run
+    /// use youchoose::ScreenSide;
+    ///
+    /// let mut menu = youchoose::Menu::new(0..100).preview_with_pos(
+    ///     |num| format!("{} squared is {}", num, num * num),
+    ///     ScreenSide::Bottom,
+    ///     0.3,
+    /// );
+    /// menu.show();
+    /// ```
+    pub fn preview_with_pos<F>(
+        self,
+        func: F,
+        side: ScreenSide,
+        width: f64,
+    ) -> Menu<'a, I, D>
+    where
+        F: Fn(D) -> String + 'static,
+    {
+        self.preview(func).preview_pos(side, width)
+    }
+
+    /// Like [`preview`](struct.Menu.html#method.preview), but the function
+    /// returns a list of text segments, each drawn with its own style.
+    ///
+    /// ```no_run
+    /// use youchoose::{Color, ItemStyle};
+    ///
+    /// let mut menu = youchoose::Menu::new(0..100).preview_styled(|num| {
+    ///     let red = ItemStyle {
+    ///         fg: Some(Color::Red),
+    ///         bold: true,
+    ///         ..ItemStyle::default()
+    ///     };
+    ///     vec![
+    ///         ("number ".to_string(), ItemStyle::default()),
+    ///         (num.to_string(), red),
+    ///     ]
+    /// });
+    /// menu.show();
+    /// ```
+    pub fn preview_styled<F>(self, func: F) -> Menu<'a, I, D>
+    where
+        F: Fn(D) -> StyledText + 'static,
+    {
+        self.set_preview(DispFunc::new(func))
+    }
+
+    /// Like [`preview`](struct.Menu.html#method.preview), but each preview
+    /// is computed on a thread of its own, so that the menu keeps responding
+    /// while a slow one runs. Until it is done, the pane shows "loading
+    /// preview", or "preview timed out" after
+    /// [`preview_timeout`](#method.preview_timeout).
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// let files = vec!["Cargo.toml", "README.md"];
+    /// let choice = youchoose::Menu::new(files.into_iter())
+    ///     .preview_threaded(youchoose::shell_preview("cat", &[]))
+    ///     .preview_timeout(Duration::from_millis(500))
+    ///     .show();
+    /// ```
+    pub fn preview_threaded<F>(self, func: F) -> Menu<'a, I, D>
+    where
+        F: Fn(D) -> String + Send + Sync + 'static,
+        D: Send + 'static,
+    {
+        let func = Arc::new(func);
+        self.set_preview(DispFunc::threaded(move |item| {
+            let func = Arc::clone(&func);
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                // The menu may have been closed in the meantime
+                let _ = sender.send(vec![(func(item), ItemStyle::default())]);
+            });
+            receiver
+        }))
+    }
+
+    /// Shows the preview pane on the right half of the screen, with the
+    /// previews computed by `func`.
+    fn set_preview(mut self, func: DispFunc<D>) -> Menu<'a, I, D> {
+        self.picked = None;
+        self.screen.set_pos(ScreenSide::Left, 0.5);
+        self.preview = Some(Preview::new(func, ScreenSide::Right, 0.5));
+        self
+    }
+
+    /// Only computes an item's preview when the preview key, `Ctrl-P` by
+    /// default, is pressed while it is hovered. Until then, the pane shows
+    /// which key to press. Computed previews are kept, so each is computed
+    /// at most once. Useful when previews are too expensive to compute
+    /// while scrolling.
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .preview(|n| format!("{} squared is {}", n, n * n))
+    ///     .manual_preview()
+    ///     .show();
+    /// ```
+    pub fn manual_preview(mut self) -> Menu<'a, I, D> {
+        match &mut self.preview {
+            Some(preview) => preview.manual = true,
+            None => self.error = Some(MenuError::NoPreview("manual_preview")),
+        }
+        self
+    }
+
+    /// Adds a keybinding that computes the hovered item's preview, with
+    /// `manual_preview`.
+    pub fn add_preview_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.preview.push(key);
+        self
+    }
+
+    /// Adds a preview keybinding, given as a key spec. See
+    /// [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_preview(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_preview_key(expect_key(spec))
+    }
+
+    /// Shows "preview timed out" once a preview has taken longer than
+    /// `timeout`. The computation is left to finish in the background, and
+    /// its preview is shown the next time the pane is drawn after that. Only
+    /// applies to previews set with
+    /// [`preview_threaded`](#method.preview_threaded).
+    pub fn preview_timeout(mut self, timeout: Duration) -> Menu<'a, I, D> {
+        match &mut self.preview {
+            Some(preview) => preview.func.timeout = Some(timeout),
+            None => self.error = Some(MenuError::NoPreview("preview_timeout")),
+        }
+        self
+    }
+
+    /// Like [`preview`](struct.Menu.html#method.preview), but the function
+    /// returns escape sequences that are passed on to the terminal as they
+    /// are, with the cursor at the top left corner inside the preview box.
+    /// This is meant for images drawn with a terminal graphics protocol,
+    /// such as Kitty's, iTerm2's or Sixel, so the terminal must support the
+    /// protocol used. The image should fit inside the box, as it isn't
+    /// clipped. Requires the `graphics` feature.
+    ///
+    /// The screen is repainted when the hovered item changes, which clears
+    /// Sixel and iTerm2 images. Kitty keeps images until they are deleted, so
+    /// the sequence should start with a delete command (`\x1b_Ga=d\x1b\\`).
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(vec!["a.png", "b.png"].into_iter())
+    ///     .preview_raw(|path| {
+    ///         // Transmit and display a PNG file with the Kitty protocol
+    ///         let encoded = "..."; // base64 of `path`
+    ///         format!("\x1b_Ga=d\x1b\\\x1b_Gf=100,t=f,a=T;{}\x1b\\", encoded)
+    ///     })
+    ///     .show();
+    /// ```
+    #[cfg(feature = "graphics")]
+    pub fn preview_raw<F>(self, func: F) -> Menu<'a, I, D>
+    where
+        F: Fn(D) -> String + 'static,
+    {
+        let mut menu = self.preview(func);
+        if let Some(prev) = &mut menu.preview {
+            prev.raw = true;
+        }
+        menu
+    }
+
+    /// Sets the position of the preview pane. The `side` parameter determines
+    /// the side on which the pane sits. The `width` parameter is a float between
+    /// `0.0` and `1.0`, inclusive. It determines the proportion of the screen that
+    /// the preview pane should use.
+    ///
+    /// The menu's side is automatically switched to opposite the preview pane's side,
+    /// and the menu's width is set to `1 - width`.
+    pub fn preview_pos(
+        mut self,
+        side: ScreenSide,
+        width: f64,
+    ) -> Menu<'a, I, D> {
+        // The preview doesn't float over the menu
+        let side = match side {
+            ScreenSide::Center => ScreenSide::Full,
+            side => side,
+        };
+        match &mut self.preview {
+            Some(preview) => {
+                self.screen.set_pos(!side, 1.0 - width);
+                preview.set_pos(side, width);
+            }
+            None => self.error = Some(MenuError::NoPreview("preview_pos")),
+        }
+        self
+    }
+
+    /// Sets the default icon of the menu. This is displayed before each entry.
+    /// It is `❯`, or `>` if [`supports_unicode`](fn.supports_unicode.html)
+    /// returns `false`.
+    pub fn icon(mut self, icon: &'a str) -> Menu<'a, I, D> {
+        self.item_icon = icon;
+        self
+    }
+
+    /// Sets the icon displayed when an item is selected in multiselect mode.
+    pub fn selected_icon(mut self, icon: &'a str) -> Menu<'a, I, D> {
+        self.chosen_item_icon = icon;
+        self
+    }
+
+    /// Sets the icon displayed before the hovered item. By default the hovered
+    /// item uses the same icon as the other items.
+    pub fn hover_icon(mut self, icon: &'a str) -> Menu<'a, I, D> {
+        self.hover_item_icon = Some(icon);
+        self
+    }
+
+    /// Sets whether the icon column is drawn. Without it, items start at the
+    /// edge of the pane, the hovered item is only shown by its highlight and
+    /// selected items are marked at the end of the row, as with
+    /// `MarkerPosition::Suffix`. Icons are shown by default.
+    pub fn show_icon(mut self, show: bool) -> Menu<'a, I, D> {
+        self.config.show_icon = show;
+        self
+    }
+
+    /// Only redraws the previously and newly hovered items and the preview
+    /// when the hover moves without scrolling the list, instead of the whole
+    /// menu. Curses already skips unchanged cells, but this avoids redrawing
+    /// every item and rewriting the preview pane on slow terminals.
+    pub fn minimal_redraw(mut self) -> Menu<'a, I, D> {
+        self.config.minimal_redraw = true;
+        self
+    }
+
+    /// Sets where on the screen an item lands when the hover jumps to it,
+    /// e.g. after a search, and where the hovered item is kept while moving
+    /// through the list. By default, jumps keep the hover at the same height
+    /// and moves keep it in the middle third of the screen.
+    pub fn jump_anchor(mut self, anchor: JumpAnchor) -> Menu<'a, I, D> {
+        self.config.jump_anchor = Some(anchor);
+        self
+    }
+
+    /// Sets how the list scrolls when the hover moves. With
+    /// `ScrollMode::Page`, the list stays put until the hover moves off the
+    /// screen, then turns a whole screen at once.
+    pub fn scroll_mode(mut self, mode: ScrollMode) -> Menu<'a, I, D> {
+        self.config.scroll_mode = mode;
+        self
+    }
+
+    /// Speeds up scrolling while the up or down key is held down, moving
+    /// more rows per press the longer it is held.
+    pub fn key_acceleration(mut self) -> Menu<'a, I, D> {
+        self.config.key_acceleration = true;
+        self
+    }
+
+    /// Sets the number of spaces between the icon and the item's text.
+    /// Defaults to 1, and 0 fits the most text on each row.
+    pub fn icon_gap(mut self, spaces: usize) -> Menu<'a, I, D> {
+        self.config.icon_gap = spaces;
+        self
+    }
+
+    /// Hides the icon column, see `show_icon`.
+    pub fn no_icon(self) -> Menu<'a, I, D> {
+        self.show_icon(false)
+    }
+
+    /// Sets where the selected icon is drawn. Defaults to
+    /// `MarkerPosition::Prefix`, which replaces the item's icon.
+    pub fn marker_position(
+        mut self,
+        position: MarkerPosition,
+    ) -> Menu<'a, I, D> {
+        self.config.marker_position = position;
+        self
+    }
+
+    /// In multiselect mode, draws a `[ ]` or `[x]` box before each item to
+    /// show whether it is selected, instead of swapping its icon.
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .multiselect()
+    ///     .checkboxes()
+    ///     .show();
+    /// ```
+    pub fn checkboxes(self) -> Menu<'a, I, D> {
+        self.checkbox_glyphs("[ ]", "[x]")
+    }
+
+    /// Like `checkboxes`, but with the given glyphs for unchecked and
+    /// checked items.
+    pub fn checkbox_glyphs(
+        mut self,
+        unchecked: &str,
+        checked: &str,
+    ) -> Menu<'a, I, D> {
+        self.config.checkboxes =
+            Some((unchecked.to_string(), checked.to_string()));
+        self
+    }
+
+    /// Sets which icon wins when the hovered item is also selected. Defaults
+    /// to `IconPriority::Chosen`.
+    pub fn icon_priority(mut self, priority: IconPriority) -> Menu<'a, I, D> {
+        self.config.icon_priority = priority;
+        self
+    }
+
+    /// Sets the text displayed on top of the preview box. It is recommended to surround the label
+    /// with spaces for aesthetic reasons. If it is not set, `" preview "` will be used.
+    pub fn preview_label(mut self, label: String) -> Menu<'a, I, D> {
+        match &mut self.preview {
+            Some(preview) => preview.set_label(label),
+            None => self.error = Some(MenuError::NoPreview("preview_label")),
+        }
+        self
+    }
+
+    /// Sets the space between the edges of the preview pane and its
+    /// contents, counting the border. The default is 1 on every side, which
+    /// is just the border. If any side is 0 the border is not drawn, along
+    /// with the label and a counter in the preview.
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .preview(|n| format!("{} squared is {}", n, n * n))
+    ///     .preview_padding(0, 0, 0, 2)
+    ///     .show();
+    /// ```
+    pub fn preview_padding(
+        mut self,
+        top: usize,
+        right: usize,
+        bottom: usize,
+        left: usize,
+    ) -> Menu<'a, I, D> {
+        match &mut self.preview {
+            Some(preview) => {
+                preview.padding =
+                    (top as i32, right as i32, bottom as i32, left as i32)
+            }
+            None => self.error = Some(MenuError::NoPreview("preview_padding")),
+        }
+        self
+    }
+
+    /// Draws a line of `glyph` in `style` between the menu and the preview
+    /// pane. It is vertical for a preview on the left or right, and
+    /// horizontal for one on the top or bottom.
+    ///
+    /// ```no_run
+    /// use youchoose::{Color, ItemStyle};
+    ///
+    /// let style = ItemStyle {
+    ///     fg: Some(Color::Blue),
+    ///     ..ItemStyle::default()
+    /// };
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .preview(|n| format!("{} squared is {}", n, n * n))
+    ///     .divider('┃', style)
+    ///     .show();
+    /// ```
+    pub fn divider(mut self, glyph: char, style: ItemStyle) -> Menu<'a, I, D> {
+        match &mut self.preview {
+            Some(preview) => preview.divider = Some((glyph, style)),
+            None => self.error = Some(MenuError::NoPreview("divider")),
+        }
+        self
+    }
+
+    /// Delays computing the preview of a newly hovered item until the hover
+    /// has stayed on it for `interval`. While waiting, the previous preview
+    /// is kept on screen. This is useful when previews are expensive, e.g.
+    /// when they run a command, and the list is scrolled through quickly.
+    pub fn preview_debounce(mut self, interval: Duration) -> Menu<'a, I, D> {
+        self.config.preview_debounce = Some(interval);
+        self
+    }
+
+    /// Caches previews in `dir`, so they are only computed once across runs.
+    /// Previews are looked up by the item's display string, or by the key
+    /// set with `preview_cache_key`. Changing `version` invalidates the
+    /// previews cached so far.
+    ///
+    /// Only use this for previews that always give the same result for the
+    /// same item.
+    #[cfg(feature = "disk-cache")]
+    pub fn preview_disk_cache<P>(
+        mut self,
+        dir: P,
+        version: &str,
+    ) -> Menu<'a, I, D>
+    where
+        P: Into<std::path::PathBuf>,
+    {
+        match &mut self.preview {
+            Some(preview) => {
+                preview.cache = Some(DiskCache {
+                    dir: dir.into(),
+                    version: version.to_string(),
+                    key: None,
+                })
+            }
+            None => {
+                self.error = Some(MenuError::NoPreview("preview_disk_cache"))
+            }
+        }
+        self
+    }
+
+    /// Sets the key previews are cached by, instead of the item's display
+    /// string. Must be called after `preview_disk_cache`.
+    #[cfg(feature = "disk-cache")]
+    pub fn preview_cache_key<F>(mut self, key: F) -> Menu<'a, I, D>
+    where
+        F: Fn(&D) -> String + 'static,
+    {
+        match self.preview.as_mut().and_then(|p| p.cache.as_mut()) {
+            Some(cache) => cache.key = Some(Box::new(key)),
+            None => {
+                self.error = Some(MenuError::NoPreview("preview_cache_key"))
+            }
+        }
+        self
+    }
+
+    /// Adds a keybinding that triggers a multiselection. This inputs an `ncurses` keycode.
+    /// All ascii keys can be set by passing the character as an `i32`. The keycodes for
+    /// special keys can be found by importing `ncurses` and using the provided constants
+    /// or by testing with the `getch` function. For example, running the following will display
+    /// the keycodes on the screen.
+    ///
+    /// ```ignore
+    /// // use ncurses::*;
+    ///
+    /// initscr();
+    /// loop {
+    ///     let c: i32 = getch();
+    ///     clear();
+    ///     if c == 'q' as i32 {break}
+    ///     addstr(&format!("Pressed key with keycode {}!", c.to_string()));
+    /// }
+    /// endwin();
+    /// ```
+    pub fn add_multiselect_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.multiselect.push(key);
+        self
+    }
+
+    /// Adds a keybinding that triggers an up movement. See [`add_multiselect_key`](struct.Menu.html#method.add_multiselect_key) for more information.
+    pub fn add_up_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.up.push(key);
+        self
+    }
+
+    /// Adds a keybinding that triggers a down movement. See [`add_multiselect_key`](struct.Menu.html#method.add_multiselect_key) for more information.
+    pub fn add_down_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.down.push(key);
+        self
+    }
+
+    /// Adds a keybinding that triggers a selection. See [`add_multiselect_key`](struct.Menu.html#method.add_multiselect_key) for more information.
+    pub fn add_select_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.select.push(key);
+        self
+    }
+
+    /// Adds a keybinding that triggers a multiselection, given as a key spec
+    /// such as `"ctrl-t"`. See [`parse_key`](fn.parse_key.html) for the
+    /// accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_multiselect(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_multiselect_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that triggers an up movement, given as a key spec
+    /// such as `"ctrl-p"`. See [`parse_key`](fn.parse_key.html) for the
+    /// accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_up(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_up_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that triggers a down movement, given as a key spec
+    /// such as `"ctrl-n"`. See [`parse_key`](fn.parse_key.html) for the
+    /// accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_down(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_down_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that triggers a selection, given as a key spec such
+    /// as `"tab"`. See [`parse_key`](fn.parse_key.html) for the accepted
+    /// names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_select(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_select_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that copies the hovered item's text to the system
+    /// clipboard without closing the menu. The result is shown in a status
+    /// bar at the bottom of the menu.
+    ///
+    /// This uses the first of `pbcopy`, `wl-copy`, `xclip` or `xsel` that
+    /// is installed.
+    #[cfg(feature = "clipboard")]
+    pub fn clipboard_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.clipboard.push(key);
+        self
+    }
+
+    /// Records every selection and deselection, which can be read with
+    /// `history` after the menu is shown.
+    ///
+    /// ```no_run
+    /// let mut menu = youchoose::Menu::new(0..100).multiselect().record_history();
+    /// let choice = menu.show();
+    /// for (index, chosen) in menu.history() {
+    ///     println!("{} {}", if *chosen { "+" } else { "-" }, index);
+    /// }
+    /// ```
+    pub fn record_history(mut self) -> Menu<'a, I, D> {
+        self.config.record_history = true;
+        self
+    }
+
+    /// Calls `func` every `interval` while the menu is shown, e.g. to
+    /// animate a status message. The menu is redrawn after each tick. The
+    /// callback can change the status bar and the title through the
+    /// `TickContext`.
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .tick(std::time::Duration::from_secs(1), |tick| {
+    ///         tick.status = Some(format!("Open for {} seconds", tick.ticks));
+    ///     })
+    ///     .show();
+    /// ```
+    pub fn tick<F>(mut self, interval: Duration, func: F) -> Menu<'a, I, D>
+    where
+        F: FnMut(&mut TickContext) + 'static,
+    {
+        self.tick = Some((interval, Box::new(func)));
+        self
+    }
+
+    /// Groups the items into collapsible sections. `depth` returns how deeply
+    /// each item is nested, and an item heads the section made of the items
+    /// after it that are nested more deeply. The select key and the
+    /// expand/collapse keys (right/left by default) open and close sections.
+    ///
+    /// Sorting with `sort_by` reorders items across sections, so the two
+    /// shouldn't be combined.
+    ///
+    /// ```no_run
+    /// let items = vec!["Fruits", "apple", "pear", "Vegetables", "leek"];
+    /// let choice = youchoose::Menu::new(items.into_iter())
+    ///     .sections(|item| if item.starts_with(char::is_uppercase) { 0 } else { 1 })
+    ///     .show();
+    /// ```
+    pub fn sections<F>(mut self, depth: F) -> Menu<'a, I, D>
+    where
+        F: Fn(&D) -> usize + 'static,
+    {
+        self.sections = Some(Box::new(depth));
+        self
+    }
+
+    /// Makes the item with the original index `index` open a submenu when it
+    /// is selected. The submenu is built by `factory` and shown in the same
+    /// curses session, over this menu. Choosing something in it ends this
+    /// menu with the item selected, while quitting it returns here. Use
+    /// `path` to see what was chosen in the submenus.
+    ///
+    /// ```no_run
+    /// let mut menu = youchoose::Menu::new(vec!["Files ›", "Quit"].into_iter())
+    ///     .submenu(0, || youchoose::Menu::new(vec!["a.txt", "b.txt"].into_iter()));
+    /// menu.show();
+    /// match menu.path().as_slice() {
+    ///     [0, file] => println!("Opening file {}", file),
+    ///     [1] => println!("Quitting"),
+    ///     _ => println!("Cancelled"),
+    /// }
+    /// ```
+    pub fn submenu<F, J, E>(
+        mut self,
+        index: usize,
+        factory: F,
+    ) -> Menu<'a, I, D>
+    where
+        F: Fn() -> Menu<'static, J, E> + 'static,
+        J: Iterator<Item = E>,
+        E: fmt::Display,
+    {
+        let open = move || {
+            let mut child = factory().borrow_curses();
+            child.try_show()?;
+            if child.state.quit {
+                return Ok(Vec::new());
+            }
+            Ok(child.path())
+        };
+        self.submenus.push((index, Box::new(open)));
+        self
+    }
+
+    /// Calls `func` with the chosen indices when the menu closes, while it
+    /// is still on the screen and before curses is torn down.
+    pub fn on_close<F>(mut self, func: F) -> Menu<'a, I, D>
+    where
+        F: Fn(&[usize]) + 'static,
+    {
+        self.on_close = Some(Box::new(func));
+        self
+    }
+
+    /// Calls `func` with the hovered item's text when `key` is pressed,
+    /// without closing the menu. The terminal is handed back while `func`
+    /// runs, so it can start programs that take it over, like an editor.
+    ///
+    /// ```no_run
+    /// use std::process::Command;
+    ///
+    /// let choice = youchoose::Menu::new(vec!["a.txt", "b.txt"].into_iter())
+    ///     .action_key('e' as i32, |path| {
+    ///         let _ = Command::new("vi").arg(path).status();
+    ///     })
+    ///     .show();
+    /// ```
+    pub fn action_key<F>(mut self, key: i32, func: F) -> Menu<'a, I, D>
+    where
+        F: Fn(&str) + 'static,
+    {
+        self.action_keys.push((key, Box::new(func)));
+        self
+    }
+
+    /// Shows a scrollbar to the right of the items. Since items are loaded
+    /// lazily, the total is only known once the iterator is drained, unless
+    /// the menu was created with `new_sized`. Until then, the scrollbar ends
+    /// in a `?`.
+    pub fn scrollbar(mut self) -> Menu<'a, I, D> {
+        self.config.scrollbar = true;
+        self
+    }
+
+    /// Draws a `▲` above the items when the list is scrolled down, and a
+    /// `▼` below them when there are more items further down. This takes
+    /// less room than a scrollbar, and unlike it never needs the total.
+    pub fn scroll_indicators(mut self) -> Menu<'a, I, D> {
+        self.config.scroll_indicators = true;
+        self
+    }
+
+    /// While searching, orders the items by how well they match the query
+    /// and hovers the best match after each keystroke, so that Enter picks
+    /// it. Items matching closer to the start of their text rank higher,
+    /// then shorter items. The first 10,000 items are loaded and ranked when
+    /// the search starts; any after them are shown below, in their original
+    /// order.
+    pub fn rank_matches(mut self) -> Menu<'a, I, D> {
+        self.config.rank_matches = true;
+        self
+    }
+
+    /// Replaces the substring search with `func`, which is given the query
+    /// and the searched text of an item and returns its score, or `None` if
+    /// it doesn't match. While searching, items with higher scores are shown
+    /// first, ranked as with [`rank_matches`](#method.rank_matches).
+    ///
+    /// ```no_run
+    /// // Only match items starting with the query, shortest first
+    /// let words = vec!["apple", "apricot", "banana"];
+    /// let choice = youchoose::Menu::new(words.into_iter())
+    ///     .search()
+    ///     .matcher(|query, text| {
+    ///         if text.starts_with(query) {
+    ///             Some(-(text.len() as i32))
+    ///         } else {
+    ///             None
+    ///         }
+    ///     })
+    ///     .show();
+    /// ```
+    pub fn matcher<F>(mut self, func: F) -> Menu<'a, I, D>
+    where
+        F: Fn(&str, &str) -> Option<i32> + 'static,
+    {
+        self.matcher = Some(Box::new(func));
+        self
+    }
+
+    /// In multiselect mode, shows the selected items above the others, in
+    /// the order they were selected. Pinned items are drawn in bold.
+    pub fn pin_selected(mut self) -> Menu<'a, I, D> {
+        self.config.pin_selected = true;
+        self
+    }
+
+    /// Sets how far the highlight of the hovered row extends past its text.
+    /// Defaults to `HighlightFill::None`, which only highlights the text.
+    /// Not applied with [`rtl`](#method.rtl).
+    ///
+    /// ```no_run
+    /// use youchoose::HighlightFill;
+    ///
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .highlight_fill(HighlightFill::Spaces)
+    ///     .show();
+    /// ```
+    pub fn highlight_fill(mut self, fill: HighlightFill) -> Menu<'a, I, D> {
+        self.config.highlight_fill = fill;
+        self
+    }
+
+    /// Caps the displayed width of each item at `width` columns, whatever
+    /// the size of the terminal. Longer items are cut short and end in `…`.
+    /// Previews and the returned indices are unaffected.
+    pub fn max_item_width(mut self, width: usize) -> Menu<'a, I, D> {
+        self.config.max_item_width = Some(width);
+        self
+    }
+
+    /// Lays the items out for right-to-left languages. Each item starts at
+    /// the right edge of the pane, with its icon to the right of the text,
+    /// and its characters are written leftwards. Only the first line of an
+    /// item is shown, and mixed direction text is not reordered.
+    pub fn rtl(mut self) -> Menu<'a, I, D> {
+        self.config.rtl = true;
+        self
+    }
+
+    /// Shows the items with the given original indices first, under a
+    /// `Recent` header and in the given order, followed by the rest of the
+    /// items. The recent items aren't repeated in the rest of the list.
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(0..100).recent(&[42, 7]).show();
+    /// ```
+    pub fn recent(mut self, indices: &[usize]) -> Menu<'a, I, D> {
+        self.recent = indices.to_vec();
+        self
+    }
+
+    /// Shows the menu in a box in the middle of the screen, taking up the
+    /// given proportion of its width and height, between `0.0` and `1.0`.
+    /// The rest of the screen is left blank. Has no effect with a preview,
+    /// which splits the screen with the menu.
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(0..10).centered(0.5).show();
+    /// ```
+    pub fn centered(mut self, size: f64) -> Menu<'a, I, D> {
+        if self.preview.is_none() {
+            self.screen.set_pos(ScreenSide::Center, size);
+        }
+        self
+    }
+
+    /// Indents the items by the given number of columns. The indent is
+    /// taken from the menu's pane, so the items never run into the preview.
+    pub fn indent(mut self, columns: u16) -> Menu<'a, I, D> {
+        self.config.indent = columns;
+        self
+    }
+
+    /// Shows a title above the items. It may span multiple lines, and lines
+    /// that don't fit the width of the menu are wrapped.
+    pub fn title(mut self, title: &str) -> Menu<'a, I, D> {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    /// Shows dimmed text below the items, above the status bar and help line.
+    /// It may span multiple lines, and lines that don't fit the width of the
+    /// menu are wrapped.
+    pub fn footer(mut self, footer: &str) -> Menu<'a, I, D> {
+        self.footer = Some(footer.to_string());
+        self
+    }
+
+    /// Sets the style the title is drawn in. Defaults to bold text.
+    ///
+    /// ```no_run
+    /// use youchoose::{Color, ItemStyle};
+    ///
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .title("Pick a number")
+    ///     .title_style(ItemStyle {
+    ///         fg: Some(Color::Cyan),
+    ///         bold: true,
+    ///         ..ItemStyle::default()
+    ///     })
+    ///     .show();
+    /// ```
+    pub fn title_style(mut self, style: ItemStyle) -> Menu<'a, I, D> {
+        self.config.title_style = style;
+        self
+    }
+
+    /// Sets the style the borders of the preview pane, the quick look box and
+    /// a centered menu are drawn in, along with their labels. Defaults to the
+    /// terminal's colors.
+    pub fn box_style(mut self, style: ItemStyle) -> Menu<'a, I, D> {
+        self.config.box_style = style;
+        self
+    }
+
+    /// Sets how the lines of the title are aligned. Defaults to
+    /// `TextAlign::Left`.
+    pub fn title_align(mut self, align: TextAlign) -> Menu<'a, I, D> {
+        self.config.title_align = align;
+        self
+    }
+
+    /// Shows how many items pass the search query and filters out of the
+    /// total, like `[12/340]`, at the given place. It is updated as the
+    /// query changes. `CounterPosition::Preview` must be set after
+    /// `preview`.
+    ///
+    /// ```no_run
+    /// use youchoose::CounterPosition;
+    ///
+    /// let choice = youchoose::Menu::new(0..340)
+    ///     .search()
+    ///     .counter(CounterPosition::Status)
+    ///     .show();
+    /// ```
+    pub fn counter(mut self, position: CounterPosition) -> Menu<'a, I, D> {
+        if position == CounterPosition::Preview && self.preview.is_none() {
+            self.error = Some(MenuError::NoPreview("counter"));
+        }
+        self.config.counter = Some(position);
+        self
+    }
+
+    /// Lets the items be searched by pressing `/` and typing a query. Only
+    /// the items containing the query, ignoring case, are shown. Enter
+    /// keeps the query and returns to the list, ESC clears it.
+    pub fn search(mut self) -> Menu<'a, I, D> {
+        self.config.search = true;
+        self
+    }
+
+    /// Opens the menu with `text` typed into the search prompt, so that only
+    /// the items matching it are shown. The query can be edited right away.
+    /// Implies [`search`](#method.search).
+    ///
+    /// With [`auto_accept_single`](#method.auto_accept_single), `show`
+    /// returns the only match without showing the menu at all.
+    ///
+    /// ```no_run
+    /// let branches = vec!["main", "feature/login", "feature/logout"];
+    /// let choice = youchoose::Menu::new(branches.into_iter())
+    ///     .query("log")
+    ///     .show();
+    /// ```
+    pub fn query(mut self, text: &str) -> Menu<'a, I, D> {
+        self.config.search = true;
+        self.state.query = text.to_string();
+        self.state.mode = Mode::Search;
+        self
+    }
+
+    /// Accepts the only item matching the search query as soon as a
+    /// keystroke narrows the list down to it, without waiting for Enter.
+    /// In multiselect mode, it is returned along with the toggled items.
+    /// Nothing is returned while the query is empty, however few items
+    /// there are. Each keystroke reads the iterator until a second match is
+    /// found.
+    pub fn auto_accept_single(mut self) -> Menu<'a, I, D> {
+        self.config.auto_accept_single = true;
+        self
+    }
+
+    /// Only matches the search query against the given tab separated field
+    /// of each item, counting from `0`, while still displaying the whole
+    /// item. Items without that field never match a query.
+    ///
+    /// ```no_run
+    /// let files = vec!["12K\tnotes.txt", "3M\tphoto.jpg"];
+    /// let choice = youchoose::Menu::new(files.into_iter())
+    ///     .search()
+    ///     .search_column(1)
+    ///     .show();
+    /// ```
+    pub fn search_column(mut self, column: usize) -> Menu<'a, I, D> {
+        self.config.search_column = Some(column);
+        self
+    }
+
+    /// Shows a message in the middle of the menu when the search query or
+    /// the active filters hide every item, instead of leaving it blank.
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .search()
+    ///     .no_match_text("No matches")
+    ///     .show();
+    /// ```
+    pub fn no_match_text(mut self, text: &str) -> Menu<'a, I, D> {
+        self.config.no_match_text = Some(text.to_string());
+        self
+    }
+
+    /// Ignores accents when matching the search query, so that `jose`
+    /// matches `José`. Requires the `accent-folding` feature.
+    #[cfg(feature = "accent-folding")]
+    pub fn fold_accents(mut self) -> Menu<'a, I, D> {
+        self.config.fold_accents = true;
+        self
+    }
+
+    /// Binds a key to an action in the given mode. These bindings take
+    /// precedence over the other keys of that mode, so e.g. a key can move
+    /// down in normal mode while typing into the query in search mode.
+    ///
+    /// Custom modes have no keys other than the ones bound here, and ESC
+    /// returns to normal mode.
+    ///
+    /// ```no_run
+    /// use youchoose::{Action, Mode};
+    ///
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .multiselect()
+    ///     .bind_mode_key(Mode::Normal, 'v' as i32, Action::SetMode(Mode::Custom("visual")))
+    ///     .bind_mode_key(Mode::Custom("visual"), 'j' as i32, Action::Down)
+    ///     .bind_mode_key(Mode::Custom("visual"), 'x' as i32, Action::Multiselect)
+    ///     .show();
+    /// ```
+    pub fn bind_mode_key(
+        mut self,
+        mode: Mode,
+        key: i32,
+        action: Action,
+    ) -> Menu<'a, I, D> {
+        self.mode_keys.push((mode, key, action));
+        self
+    }
+
+    /// Like `bind_mode_key`, but the key is given as a key spec. See
+    /// [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_mode(
+        self,
+        mode: Mode,
+        spec: &str,
+        action: Action,
+    ) -> Menu<'a, I, D> {
+        self.bind_mode_key(mode, expect_key(spec), action)
+    }
+
+    /// Adds a keybinding that deselects every item in multiselect mode. By
+    /// default, `Ctrl-\` is bound to this.
+    pub fn add_clear_selection_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.clear_selection.push(key);
+        self
+    }
+
+    /// Adds a keybinding that deselects every item, given as a key spec. See
+    /// [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_clear_selection(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_clear_selection_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that reverts the last selection toggle in
+    /// multiselect mode. By default, `Ctrl-Z` is bound to this.
+    pub fn add_undo_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.undo.push(key);
+        self
+    }
+
+    /// Adds an undo keybinding, given as a key spec. See
+    /// [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_undo(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_undo_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that reapplies the last undone selection toggle. By
+    /// default, `Ctrl-Y` is bound to this.
+    pub fn add_redo_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.redo.push(key);
+        self
+    }
+
+    /// Adds a redo keybinding, given as a key spec. See
+    /// [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_redo(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_redo_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that shows the hovered item's preview in a box
+    /// covering most of the screen. Pressing it again closes the box, other
+    /// keys are ignored while it is open. Requires a preview.
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .preview(|n| format!("{} squared is {}", n, n * n))
+    ///     .add_quicklook_key(' ' as i32)
+    ///     .show();
+    /// ```
+    pub fn add_quicklook_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.quicklook.push(key);
+        self
+    }
+
+    /// Adds a quick look keybinding, given as a key spec. See
+    /// [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_quicklook(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_quicklook_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that raises the hovered item's count, with
+    /// `quantities`.
+    pub fn add_increment_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.increment.push(key);
+        self
+    }
+
+    /// Adds an increment keybinding, given as a key spec. See
+    /// [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_increment(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_increment_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that lowers the hovered item's count, with
+    /// `quantities`.
+    pub fn add_decrement_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.decrement.push(key);
+        self
+    }
+
+    /// Adds a decrement keybinding, given as a key spec. See
+    /// [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_decrement(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_decrement_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that puts the last picked item back in the list,
+    /// with `transfer`.
+    pub fn add_put_back_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.put_back.push(key);
+        self
+    }
+
+    /// Adds a put back keybinding, given as a key spec. See
+    /// [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_put_back(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_put_back_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that hovers the next selected item in multiselect
+    /// mode, wrapping around to the first one.
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(0..1000)
+    ///     .multiselect()
+    ///     .add_next_selected_key('n' as i32)
+    ///     .add_prev_selected_key('N' as i32)
+    ///     .show();
+    /// ```
+    pub fn add_next_selected_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.next_selected.push(key);
+        self
+    }
+
+    /// Adds a next selected keybinding, given as a key spec. See
+    /// [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_next_selected(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_next_selected_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that hovers the previous selected item in
+    /// multiselect mode, wrapping around to the last one.
+    pub fn add_prev_selected_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.prev_selected.push(key);
+        self
+    }
+
+    /// Adds a previous selected keybinding, given as a key spec. See
+    /// [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_prev_selected(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_prev_selected_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that returns the toggled items as they are. Unlike
+    /// the select key, the hovered item is not added to them.
+    pub fn add_accept_selection_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.accept_selection.push(key);
+        self
+    }
+
+    /// Adds a keybinding that returns only the hovered item, ignoring the
+    /// toggled items.
+    ///
+    /// Most terminals send the same code for Enter and Ctrl-Enter, so a key
+    /// such as `"ctrl-o"` works better for this.
+    pub fn add_accept_current_key(mut self, key: i32) -> Menu<'a, I, D> {
+        self.keys.accept_current.push(key);
+        self
+    }
+
+    /// Adds a keybinding that returns the toggled items, given as a key
+    /// spec. See [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_accept_selection(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_accept_selection_key(expect_key(spec))
+    }
+
+    /// Adds a keybinding that returns only the hovered item, given as a key
+    /// spec. See [`parse_key`](fn.parse_key.html) for the accepted names.
+    ///
+    /// Panics if the spec is not recognized.
+    pub fn bind_accept_current(self, spec: &str) -> Menu<'a, I, D> {
+        self.add_accept_current_key(expect_key(spec))
+    }
+
+    /// In multiselect mode, makes the select key take the hovered item off
+    /// the end of the selection, and keep the menu open, if it is the item
+    /// selected last. By default, and for any other selected item, the
+    /// select key returns the selection as it is.
+    pub fn select_key_toggles_last(mut self) -> Menu<'a, I, D> {
+        self.config.select_key_toggles_last = true;
+        self
+    }
+
+    /// In multiselect mode, makes the select key return only the toggled
+    /// items, or the hovered item if nothing has been toggled. By default,
+    /// the hovered item is added to the toggled items.
+    pub fn enter_selects_hover(mut self) -> Menu<'a, I, D> {
+        self.config.enter_selects_hover = true;
+        self
+    }
+
+    /// Once items are chosen, lists them in a box and asks to confirm them
+    /// before returning. `y` returns them, while `n` or escape goes back to
+    /// the list as it was. Nothing is asked when the menu ends with nothing
+    /// chosen.
+    ///
+    /// ```no_run
+    /// let disks = vec!["/dev/sda", "/dev/sdb"];
+    /// let choice = youchoose::Menu::new(disks.into_iter()).confirm().show();
+    /// ```
+    pub fn confirm(mut self) -> Menu<'a, I, D> {
+        self.config.confirm = true;
+        self
+    }
+
+    /// Replaces the menu's settings, including any set by the other builder
+    /// methods so far.
+    pub fn with_config(mut self, config: MenuSettings) -> Menu<'a, I, D> {
+        self.config = config;
+        self
+    }
+
+    /// Replaces the menu's keybindings, including any added by the other
+    /// builder methods so far.
+    pub fn with_keys(mut self, keys: Keys) -> Menu<'a, I, D> {
+        self.keys = keys;
+        self
+    }
+
+    /// Allow multiple items to be selected from the menu.
+    pub fn multiselect(mut self) -> Menu<'a, I, D> {
+        self.config.multiselect = true;
+        self
+    }
+
+    /// Lets each item be chosen several times, e.g. to pick quantities.
+    /// `+` and `-` change the hovered item's count, which is shown next to
+    /// it as `(x2)`. Use `counts` to get the counts back. Implies
+    /// [`multiselect`](#method.multiselect).
+    ///
+    /// ```no_run
+    /// let fruit = vec!["apple", "banana", "cherry"];
+    /// let mut menu = youchoose::Menu::new(fruit.into_iter()).quantities();
+    /// menu.show();
+    /// for (index, count) in menu.counts() {
+    ///     println!("{} of item {}", count, index);
+    /// }
+    /// ```
+    pub fn quantities(mut self) -> Menu<'a, I, D> {
+        self.config.multiselect = true;
+        self.config.quantities = true;
+        self
+    }
+
+    /// Makes the select key move the hovered item out of the list instead of
+    /// ending the menu, for putting items in order. Backspace puts the last
+    /// picked item back. The menu ends when every item has been picked or
+    /// it is quit, and returns the picked items in the order they were
+    /// picked.
+    ///
+    /// The picked items are listed in a box on the right half of the
+    /// screen, unless the menu has a preview, which takes its place.
+    ///
+    /// ```no_run
+    /// let tasks = vec!["write", "review", "merge", "deploy"];
+    /// let order = youchoose::Menu::new(tasks.into_iter()).transfer().show();
+    /// ```
+    pub fn transfer(mut self) -> Menu<'a, I, D> {
+        self.config.transfer = true;
+        if self.preview.is_none() {
+            self.screen.set_pos(ScreenSide::Left, 0.5);
+            self.picked = Some(Screen::new(ScreenSide::Right, 0.5));
+        }
+        self
+    }
+
+    /// Selects every item when the menu is shown, so that unwanted items can
+    /// be deselected instead. Only has an effect in multiselect mode.
+    ///
+    /// This reads the whole iterator before the menu is drawn, so items are
+    /// no longer loaded lazily or streamed. An iterator that is known to be
+    /// infinite, such as `0..`, makes `try_show` return an error.
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .multiselect()
+    ///     .start_all_selected()
+    ///     .show();
+    /// ```
+    pub fn start_all_selected(mut self) -> Menu<'a, I, D> {
+        if self.iter.size_hint().0 == usize::MAX {
+            self.error = Some(MenuError::Infinite("start_all_selected"));
+        }
+        self.config.start_all_selected = true;
+        self
+    }
+
+    /// Show a dimmed description below each item for which `func` returns
+    /// `Some`. An item and its description are navigated as one entry.
+    pub fn description_with<F>(mut self, func: F) -> Menu<'a, I, D>
+    where
+        F: Fn(&D) -> Option<String> + 'static,
+    {
+        self.describe = Some(Box::new(func));
+        self
+    }
+
+    /// Match the search query against the text `func` returns for each
+    /// item instead of the displayed text, e.g. to find items by tags that
+    /// are not shown.
+    ///
+    /// ```no_run
+    /// let choice = youchoose::Menu::new(1..100)
+    ///     .search()
+    ///     .search_with(|n| format!("{} {}", n, if n % 2 == 0 { "even" } else { "odd" }))
+    ///     .show();
+    /// ```
+    pub fn search_with<F>(mut self, func: F) -> Menu<'a, I, D>
+    where
+        F: Fn(&D) -> String + 'static,
+    {
+        self.search_key = Some(Box::new(func));
+        self
+    }
+
+    /// Registers a filter that is switched on and off by pressing `key`.
+    /// While it is on, only the items for which `pred` returns `true` are
+    /// shown. When several filters are on, an item must pass all of them to
+    /// be shown. The indices returned by `show` are not affected by
+    /// filtering.
+    pub fn filter_toggle<F>(mut self, key: i32, pred: F) -> Menu<'a, I, D>
+    where
+        F: Fn(&D) -> bool + 'static,
+    {
+        self.filters.push(Filter {
+            key,
+            pred: Box::new(pred),
+            active: false,
+        });
+        self
+    }
+
+    /// Sort the items with the given comparison function before displaying
+    /// them. The indices returned by `show` still refer to the positions of
+    /// the items in the original iterable.
+    ///
+    /// Sorting needs every item up front, so the iterable is consumed entirely
+    /// when the menu is shown instead of lazily. Do not use this with infinite
+    /// iterators.
+    pub fn sort_by<F>(mut self, cmp: F) -> Menu<'a, I, D>
+    where
+        F: Fn(&D, &D) -> Ordering + 'static,
+    {
+        self.sort_by = Some(Box::new(cmp));
+        self
+    }
+
+    /// Show a line at the bottom of the screen listing the keybindings.
+    pub fn show_help(mut self) -> Menu<'a, I, D> {
+        self.config.help = true;
+        self
+    }
+
+    /// Display the menu right away and fill it in as the iterable produces
+    /// items, instead of waiting for a screenful of items up front. A spinner
+    /// is shown below the loaded items until the iterable is exhausted.
+    ///
+    /// This is useful for slow iterables, such as ones that read a large
+    /// directory. Each item is only loaded once, so the indices returned by
+    /// `show` are not affected. The iterable is still read between key
+    /// presses, for a short while each time; use `new_streaming` to read it
+    /// on a worker thread instead.
+    pub fn streaming(mut self) -> Menu<'a, I, D> {
+        self.config.streaming = true;
+        self
+    }
+
+    /// Label the visible items with the keys `1`-`9`, followed by the letters
+    /// that are not bound to anything else. Pressing one of these keys selects
+    /// the corresponding item and closes the menu.
+    pub fn hotkeys(mut self) -> Menu<'a, I, D> {
+        self.config.hotkeys = true;
+        self
+    }
+
+    /// Without `multiselect`, pressing a digit from `1` to `9` selects the
+    /// item on that row of the screen and closes the menu, without labelling
+    /// the rows like `hotkeys` does. Digits bound to other keys keep their
+    /// binding, and digits typed while searching go into the query.
+    pub fn instant_select(mut self) -> Menu<'a, I, D> {
+        self.config.instant_select = true;
+        self
+    }
+
+    /// Sets what is returned when the menu is quit with `ESC` or `q` before
+    /// anything was chosen. Defaults to `DefaultChoice::Empty`.
+    ///
+    /// ```no_run
+    /// use youchoose::DefaultChoice;
+    ///
+    /// let choice = youchoose::Menu::new(0..100)
+    ///     .default_on_quit(DefaultChoice::Index(usize::MAX))
+    ///     .show();
+    /// if choice == [usize::MAX] {
+    ///     println!("Cancelled");
+    /// }
+    /// ```
+    pub fn default_on_quit(mut self, choice: DefaultChoice) -> Menu<'a, I, D> {
+        self.config.default_on_quit = choice;
+        self
+    }
+
+    /// Sets the order in which the selected indices are returned. Defaults
+    /// to `Order::AsToggled`.
+    pub fn selection_order(mut self, order: Order) -> Menu<'a, I, D> {
+        self.config.selection_order = order;
+        self
+    }
+
+    /// Use a curses session that has already been initialized by the caller,
+    /// for embedding the menu in a larger `ncurses` application. `show` will
+    /// not call `initscr` or `endwin`; instead, the screen contents, colors
+    /// and terminal modes of the host are restored when it returns.
+    ///
+    /// The host should have called `start_color` for the menu to be drawn in
+    /// color. This takes precedence over [`inline`](#method.inline).
+    pub fn borrow_curses(mut self) -> Menu<'a, I, D> {
+        self.config.borrow_curses = true;
+        self
+    }
+
+    /// Display the menu inline, starting at the row the cursor is on and
+    /// using at most `rows` lines below it, instead of taking over the whole
+    /// terminal. The contents of the terminal are left intact on exit.
+    ///
+    /// If the cursor position cannot be determined (e.g. stdin is not a
+    /// terminal), the menu is anchored at the top of the screen. `show`
+    /// fails with `MenuError::NoRows` if `rows` is zero.
+    pub fn inline(mut self, rows: usize) -> Menu<'a, I, D> {
+        if rows == 0 {
+            self.error = Some(MenuError::NoRows);
+        }
+        self.config.inline = Some(rows);
+        self
+    }
+
+    /// Turn the chosen items into something else before they are returned.
+    /// `map` gets the chosen indices and the displayed text of each, in the
+    /// same order, and its result is what `show` returns.
+    ///
+    /// ```no_run
+    /// let mut menu = youchoose::Menu::new(vec!["red", "green", "blue"].into_iter())
+    ///     .multiselect()
+    ///     .map_result(|_, reprs| reprs.join(","));
+    /// println!("Colors: {}", menu.show());
+    /// ```
+    pub fn map_result<R, F>(self, map: F) -> MappedMenu<'a, I, D, F>
+    where
+        F: Fn(&[usize], &[String]) -> R,
+    {
+        MappedMenu { menu: self, map }
+    }
+
+    /// The chosen items as JSON, for other programs to read: an object with
+    /// the chosen `indices` and the displayed text of each in `items`.
+    ///
+    /// ```no_run
+    /// let mut menu = youchoose::Menu::new(vec!["a", "b"].into_iter());
+    /// menu.show();
+    /// // e.g. {"indices":[1],"items":["b"]}
+    /// println!("{}", menu.selection_json());
+    /// ```
+    pub fn selection_json(&self) -> String {
+        let (indices, items): (Vec<String>, Vec<String>) = self
+            .item_indices(self.selection())
+            .into_iter()
+            .filter_map(|i| {
+                self.repr_of(i)
+                    .map(|repr| (i.to_string(), json_string(repr)))
+            })
+            .unzip();
+        format!(
+            "{{\"indices\":[{}],\"items\":[{}]}}",
+            indices.join(","),
+            items.join(",")
+        )
+    }
+
+    /// The displayed text of the item at `index` in the original iterable.
+    fn repr_of(&self, index: usize) -> Option<&String> {
+        self.state
+            .items
+            .iter()
+            .find(|item| item.index == index)
+            .map(Item::string)
+    }
+}
+
+impl<'a, J, D, M> Menu<'a, WithMeta<J, M>, D>
+where
+    D: fmt::Display,
+    J: Iterator<Item = (D, M)>,
+{
+    /// Create a menu of items paired with arbitrary data, such as ids or
+    /// tags. Only the first element of each pair is displayed. Use
+    /// `show_with_meta` to get the data of the chosen items back.
+    ///
+    /// ```no_run
+    /// let users = vec![("alice", 1001), ("bob", 1002)];
+    /// let mut menu = youchoose::Menu::new_with_meta(users.into_iter());
+    /// for (index, uid) in menu.show_with_meta() {
+    ///     println!("Chose item {} with uid {}", index, uid);
+    /// }
+    /// ```
+    pub fn new_with_meta(iter: J) -> Menu<'a, WithMeta<J, M>, D> {
+        Menu::new(WithMeta {
+            iter,
+            meta: Vec::new(),
+        })
+    }
+
+    /// Like `show`, but returns each chosen index along with its data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the menu could not be shown.
+    pub fn show_with_meta(&mut self) -> Vec<(usize, M)>
+    where
+        M: Clone,
+    {
+        self.try_show_with_meta()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `try_show`, but returns each chosen index along with its data.
+    pub fn try_show_with_meta(&mut self) -> Result<Vec<(usize, M)>, MenuError>
+    where
+        M: Clone,
+    {
+        let selection = self.try_show()?;
+        let meta = &self.iter.meta;
+        Ok(self
+            .item_indices(selection)
+            .into_iter()
+            .map(|i| (i, meta[i].clone()))
+            .collect())
+    }
+}
+
+impl<'a, 's, T> Menu<'a, SliceItems<'s, T>, &'s T>
+where
+    T: fmt::Display,
+{
+    /// Create a menu of the items in `slice`. Use `show_values` to get the
+    /// chosen items back instead of their indices.
+    ///
+    /// ```no_run
+    /// let names = vec!["alice".to_string(), "bob".to_string()];
+    /// let mut menu = youchoose::Menu::from_slice(&names);
+    /// for name in menu.show_values() {
+    ///     println!("Chose {}", name);
+    /// }
+    /// ```
+    pub fn from_slice(slice: &'s [T]) -> Menu<'a, SliceItems<'s, T>, &'s T> {
+        Menu::new_sized(SliceItems { slice, next: 0 })
+    }
+
+    /// Like `show`, but returns clones of the chosen items.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the menu could not be shown.
+    pub fn show_values(&mut self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.try_show_values()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `try_show`, but returns clones of the chosen items.
+    pub fn try_show_values(&mut self) -> Result<Vec<T>, MenuError>
+    where
+        T: Clone,
+    {
+        let selection = self.try_show()?;
+        let slice = self.iter.slice;
+        Ok(self
+            .item_indices(selection)
+            .into_iter()
+            .map(|i| slice[i].clone())
+            .collect())
+    }
+}
+
+impl<'a, D> Menu<'a, StreamItems<D>, D>
+where
+    D: fmt::Display + Send + 'static,
+{
+    /// Like `new` with `streaming`, but the iterable is read on a worker
+    /// thread, so that the menu keeps responding while a slow item is
+    /// produced. The items read so far are added each time the menu is
+    /// drawn.
+    ///
+    /// ```no_run
+    /// let files = std::fs::read_dir("/usr/bin")
+    ///     .unwrap()
+    ///     .filter_map(Result::ok)
+    ///     .map(|entry| entry.path().display().to_string());
+    /// let choice = youchoose::Menu::new_streaming(files).show();
+    /// ```
+    pub fn new_streaming<J>(iter: J) -> Menu<'a, StreamItems<D>, D>
+    where
+        J: Iterator<Item = D> + Send + 'static,
+    {
+        let mut menu = Menu::new(StreamItems::new(iter)).streaming();
+        menu.poll = Some(StreamItems::poll);
+        menu
+    }
+}
+
+/// The events of a shown menu, returned by `Menu::events`.
+pub struct Events<'m, 'a, I, D>
+where
+    D: fmt::Display,
+    I: Iterator<Item = D>,
+{
+    menu: &'m mut Menu<'a, I, D>,
+    // taken once the menu has ended
+    curses: Option<Curses>,
+}
+
+impl<'m, 'a, I, D> Iterator for Events<'m, 'a, I, D>
+where
+    D: fmt::Display,
+    I: Iterator<Item = D>,
+{
+    type Item = MenuEvent;
+
+    fn next(&mut self) -> Option<MenuEvent> {
+        self.curses.as_ref()?;
+        let menu = &mut *self.menu;
+        let hovered = menu.hovered_index();
+        let selection = menu.selection.clone();
+
+        // Timeouts only update the spinner, previews and ticks
+        let key = loop {
+            timeout(menu.poll_delay());
+            let key = menu.screen.get_key();
+            if !menu.handle_key(key) {
+                break None;
+            }
+            menu.redraw();
+            if key != ERR {
+                break Some(key);
+            }
+        };
+
+        let key = match key {
+            Some(key) => key,
+            None => {
+                let chosen = menu.selection();
+                if let Some(on_close) = &menu.on_close {
+                    on_close(&chosen);
+                }
+                let quit = menu.state.quit;
+                self.close();
+                return Some(if quit {
+                    MenuEvent::Cancelled(chosen)
+                } else {
+                    MenuEvent::Chosen(chosen)
+                });
+            }
+        };
+        Some(if menu.selection != selection {
+            MenuEvent::SelectionChanged(menu.selection())
+        } else if menu.hovered_index() != hovered {
+            match menu.hovered_index() {
+                Some(index) => MenuEvent::Moved(index),
+                None => MenuEvent::Key(key),
+            }
+        } else {
+            MenuEvent::Key(key)
+        })
+    }
+}
+
+impl<'m, 'a, I, D> Drop for Events<'m, 'a, I, D>
+where
+    D: fmt::Display,
+    I: Iterator<Item = D>,
+{
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl<'m, 'a, I, D> Events<'m, 'a, I, D>
+where
+    D: fmt::Display,
+    I: Iterator<Item = D>,
+{
+    /// The menu, to change it between events.
+    pub fn menu(&mut self) -> &mut Menu<'a, I, D> {
+        self.menu
+    }
+
+    /// Restores the terminal. The menu is laid out again when it is next
+    /// drawn.
+    fn close(&mut self) {
+        if let Some(curses) = self.curses.take() {
+            curses.close();
+            self.menu.state.prepared = false;
+        }
+    }
+}
+
+/// The iterator of a menu created with `Menu::new_with_meta`. It yields the
+/// displayed half of each pair and keeps the data of every item it yielded.
+pub struct WithMeta<J, M> {
+    iter: J,
+    meta: Vec<M>,
+}
+
+impl<J, D, M> Iterator for WithMeta<J, M>
+where
+    J: Iterator<Item = (D, M)>,
+{
+    type Item = D;
+
+    fn next(&mut self) -> Option<D> {
+        let (item, meta) = self.iter.next()?;
+        self.meta.push(meta);
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// A menu whose result is transformed, returned by `Menu::map_result`.
+pub struct MappedMenu<'a, I, D, F>
+where
+    D: fmt::Display,
+    I: Iterator<Item = D>,
+{
+    menu: Menu<'a, I, D>,
+    map: F,
+}
+
+impl<'a, I, D, F, R> MappedMenu<'a, I, D, F>
+where
+    D: fmt::Display,
+    I: Iterator<Item = D>,
+    F: Fn(&[usize], &[String]) -> R,
+{
+    /// Like `Menu::show`, but returns the mapped selection.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the menu could not be shown.
+    pub fn show(&mut self) -> R {
+        self.try_show().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `Menu::try_show`, but returns the mapped selection.
+    pub fn try_show(&mut self) -> Result<R, MenuError> {
+        let selection = self.menu.try_show()?;
+        let indices = self.menu.item_indices(selection);
+        let reprs: Vec<String> = indices
+            .iter()
+            .filter_map(|&i| self.menu.repr_of(i).cloned())
+            .collect();
+        Ok((self.map)(&indices, &reprs))
+    }
+
+    /// The wrapped menu.
+    pub fn menu(&mut self) -> &mut Menu<'a, I, D> {
+        &mut self.menu
+    }
+}
+
+/// The iterator of a menu created with `Menu::from_slice`. It yields a
+/// reference to each item of the slice.
+pub struct SliceItems<'s, T> {
+    slice: &'s [T],
+    next: usize,
+}
+
+impl<'s, T> Iterator for SliceItems<'s, T> {
+    type Item = &'s T;
+
+    fn next(&mut self) -> Option<&'s T> {
+        let item = self.slice.get(self.next)?;
+        self.next += 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.slice.len() - self.next;
+        (left, Some(left))
+    }
+}
+
+impl<'s, T> ExactSizeIterator for SliceItems<'s, T> {}
+
+/// The iterator of a menu created with `Menu::new_streaming`. It yields the
+/// items read by a worker thread, waiting for the next one if needed.
+pub struct StreamItems<D> {
+    receiver: Receiver<D>,
+}
+
+impl<D> StreamItems<D>
+where
+    D: Send + 'static,
+{
+    /// Starts reading `iter` on a worker thread, e.g. to pass to
+    /// `set_items` on a menu created with `Menu::new_streaming`.
+    pub fn new<J>(iter: J) -> StreamItems<D>
+    where
+        J: Iterator<Item = D> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for item in iter {
+                // Stop reading once the menu is gone
+                if sender.send(item).is_err() {
+                    break;
+                }
+            }
+        });
+        StreamItems { receiver }
+    }
+
+    /// Takes the next item if the worker thread has read it already.
+    fn poll(&mut self) -> Result<D, TryRecvError> {
+        self.receiver.try_recv()
+    }
+}
+
+impl<D> Iterator for StreamItems<D> {
+    type Item = D;
+
+    fn next(&mut self) -> Option<D> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// Two menus side by side, for picking one item from each, e.g. a source
+/// and a destination. Tab switches between the panes. Picking an item moves
+/// on to the other pane, until both have an item picked.
+///
+/// The menus keep their own settings, but are always shown in the left and
+/// right halves of the screen, so they shouldn't have previews. Titles, help
+/// lines and search prompts span the whole width, so they should only be
+/// set on one of them. Curses is set up as configured on the left menu.
+///
+/// ```no_run
+/// let sources = youchoose::Menu::new(vec!["a.txt", "b.txt"].into_iter());
+/// let dirs = youchoose::Menu::new(vec!["docs", "backup"].into_iter());
+/// match youchoose::DualMenu::new(sources, dirs).show() {
+///     Some((file, dir)) => println!("Move {} to {}", file, dir),
+///     None => println!("Cancelled"),
+/// }
+/// ```
+pub struct DualMenu<'a, I, D, J, E>
+where
+    D: fmt::Display,
+    I: Iterator<Item = D>,
+    E: fmt::Display,
+    J: Iterator<Item = E>,
+{
+    left: Menu<'a, I, D>,
+    right: Menu<'a, J, E>,
+    // whether the right pane has focus
+    right_focused: bool,
+    // original index of the item picked in each pane
+    picks: (Option<usize>, Option<usize>),
+}
+
+impl<'a, I, D, J, E> DualMenu<'a, I, D, J, E>
+where
+    D: fmt::Display,
+    I: Iterator<Item = D>,
+    E: fmt::Display,
+    J: Iterator<Item = E>,
+{
+    /// Puts the two menus side by side, with the left one focused.
+    pub fn new(
+        mut left: Menu<'a, I, D>,
+        mut right: Menu<'a, J, E>,
+    ) -> DualMenu<'a, I, D, J, E> {
+        left.screen.set_pos(ScreenSide::Left, 0.5);
+        right.screen.set_pos(ScreenSide::Right, 0.5);
+        right.state.focused = false;
+        DualMenu {
+            left,
+            right,
+            right_focused: false,
+            picks: (None, None),
+        }
+    }
+
+    /// Shows both menus and returns the original indices of the items
+    /// picked from the left and right menu, or `None` if the menu was quit
+    /// first. Panics if curses could not be set up, see `try_show`.
+    pub fn show(&mut self) -> Option<(usize, usize)> {
+        self.try_show().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `show`, but returns an error if the terminal could not be set up
+    /// or either menu was misconfigured.
+    pub fn try_show(&mut self) -> Result<Option<(usize, usize)>, MenuError> {
+        if let Some(err) = self.left.error.take() {
+            return Err(err);
+        }
+        if let Some(err) = self.right.error.take() {
+            return Err(err);
+        }
+
+        let curses = self.left.open_curses()?;
+        self.left.prepare(curses.frame);
+        self.right.prepare(curses.frame);
+        self.draw();
+
+        let picks = loop {
+            timeout(self.poll_delay());
+            match self.handle_key(getch()) {
+                Some(true) => (),
+                Some(false) => break None,
+                None => break self.picks(),
+            }
+            self.draw();
+        };
+
+        curses.close();
+        Ok(picks)
+    }
+
+    /// Handles a key, returning whether to keep going, or `None` once an
+    /// item has been picked in both panes.
+    fn handle_key(&mut self, key: i32) -> Option<bool> {
+        if key == ERR {
+            self.left.handle_key(key);
+            self.right.handle_key(key);
+            return Some(true);
+        }
+        if key == 9 {
+            // Tab
+            self.focus(!self.right_focused);
+            return Some(true);
+        }
+
+        let (mode, quicklook) = if self.right_focused {
+            (self.right.state.mode, self.right.state.quicklook)
+        } else {
+            (self.left.state.mode, self.left.state.quicklook)
+        };
+        let quit = (key == 27 || key == 113) // ESC or q
+            && mode == Mode::Normal
+            && !quicklook;
+        if quit {
+            return Some(false);
+        }
+
+        if self.right_focused {
+            if !self.right.handle_key(key) {
+                self.picks.1 = self.right.selection().last().copied();
+            }
+        } else if !self.left.handle_key(key) {
+            self.picks.0 = self.left.selection().last().copied();
+        }
+        match self.picks {
+            (Some(_), Some(_)) => None,
+            (Some(_), None) => {
+                self.focus(true);
+                Some(true)
+            }
+            (None, Some(_)) => {
+                self.focus(false);
+                Some(true)
+            }
+            (None, None) => Some(true),
+        }
+    }
+
+    fn focus(&mut self, right: bool) {
+        self.right_focused = right;
+        self.left.state.focused = !right;
+        self.right.state.focused = right;
+    }
+
+    fn picks(&self) -> Option<(usize, usize)> {
+        match self.picks {
+            (Some(left), Some(right)) => Some((left, right)),
+            _ => None,
+        }
+    }
+
+    /// The shorter of the two menus' waits for a key, `-1` being
+    /// indefinite.
+    fn poll_delay(&self) -> i32 {
+        match (self.left.poll_delay(), self.right.poll_delay()) {
+            (-1, delay) | (delay, -1) => delay,
+            (left, right) => left.min(right),
+        }
+    }
+
+    fn draw(&mut self) {
+        erase();
+        self.share_rows(&Screen::get_size());
+        self.left.refresh();
+        self.right.refresh();
+    }
+
+    /// Reserves as many rows above and below the items in both panes as
+    /// either needs, so that their rows line up and neither draws items
+    /// where the other has its title, prompt or help.
+    fn share_rows(&mut self, size: &Pair) {
+        let (left, right) =
+            (self.left.own_rows(size), self.right.own_rows(size));
+        let rows = (left.0.max(right.0), left.1.max(right.1));
+        self.left.state.shared_rows = rows;
+        self.right.state.shared_rows = rows;
+    }
+}
+
+struct MenuState<'a> {
+    hover: usize,
+    start: usize,
+    items: Vec<Item<'a>>,
+    // positions in `items` of the items that pass the active filters, in
+    // display order
+    visible: Vec<usize>,
+    // whether the iterator has run out of items
+    exhausted: bool,
+    // current frame of the loading spinner
+    spinner: usize,
+    // the hovered item and when the hover moved onto it
+    hovered: Option<usize>,
+    hover_since: Instant,
+    // the item whose preview was last drawn
+    shown_preview: Option<usize>,
+    // message shown in the status bar until the next key press
+    status: Option<String>,
+    // the search query, and whether keys are currently typed into it
+    query: String,
+    mode: Mode,
+    // whether the panes have been laid out and the first items loaded
+    prepared: bool,
+    // number of ticks so far and when the next one is due
+    ticks: u64,
+    next_tick: Option<Instant>,
+    // whether the hovered item's preview is shown over the menu
+    quicklook: bool,
+    // direction and time of the last move, and the number of quick moves
+    // in a row before it
+    last_move: Option<(i32, Instant)>,
+    streak: u32,
+    // whether the hovered item is highlighted, false in the unfocused pane
+    // of a `DualMenu`
+    focused: bool,
+    // item and position of the raw preview on the screen, and whether it
+    // still has to be written
+    raw_shown: Option<(usize, i32, i32)>,
+    raw_pending: bool,
+    // whether the menu was quit rather than an item chosen
+    quit: bool,
+    // (row in `visible`, first line, line after it) of each item on screen
+    row_spans: Vec<(usize, i32, i32)>,
+    // the row the hover moved from, if nothing else changed since drawing
+    moved_from: Option<usize>,
+    // selection and length of the undo stack from before the choice that is
+    // waiting to be confirmed
+    confirming: Option<(Vec<usize>, usize)>,
+    // rows kept free at the (top, bottom) at the least, so that the panes
+    // of a `DualMenu` line up
+    shared_rows: (i32, i32),
+}
+
+impl<'a> MenuState<'a> {
+    fn new() -> MenuState<'a> {
+        MenuState {
+            hover: 0,
+            start: 0,
+            items: Vec::new(),
+            visible: Vec::new(),
+            exhausted: false,
+            spinner: 0,
+            hovered: None,
+            hover_since: Instant::now(),
+            shown_preview: None,
+            status: None,
+            query: String::new(),
+            mode: Mode::Normal,
+            prepared: false,
+            ticks: 0,
+            next_tick: None,
+            quicklook: false,
+            last_move: None,
+            streak: 0,
+            focused: true,
+            raw_shown: None,
+            raw_pending: false,
+            quit: false,
+            row_spans: Vec::new(),
+            moved_from: None,
+            confirming: None,
+            shared_rows: (0, 0),
+        }
+    }
+}
+
+/// The keybindings of a menu, as `ncurses` keycodes. Each action can be
+/// bound to any number of keys.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keys {
+    pub down: Vec<i32>,
+    pub up: Vec<i32>,
+    pub select: Vec<i32>,
+    pub multiselect: Vec<i32>,
+    /// Copies the hovered item to the clipboard. Requires the `clipboard`
+    /// feature.
+    pub clipboard: Vec<i32>,
+    /// Starts typing a search query, if search is enabled.
+    pub search: Vec<i32>,
+    /// Returns the toggled items as they are, without adding the hovered
+    /// item.
+    pub accept_selection: Vec<i32>,
+    /// Returns only the hovered item, ignoring the toggled items.
+    pub accept_current: Vec<i32>,
+    /// Deselects every item in multiselect mode.
+    pub clear_selection: Vec<i32>,
+    /// Opens the hovered section. The select key toggles it as well.
+    pub expand: Vec<i32>,
+    /// Closes the hovered section.
+    pub collapse: Vec<i32>,
+    /// Reverts the last selection toggle in multiselect mode.
+    pub undo: Vec<i32>,
+    /// Reapplies the last undone selection toggle.
+    pub redo: Vec<i32>,
+    /// Shows the hovered item's preview over the menu, until pressed again.
+    pub quicklook: Vec<i32>,
+    /// Raises the hovered item's count, with `quantities`.
+    pub increment: Vec<i32>,
+    /// Lowers the hovered item's count, with `quantities`.
+    pub decrement: Vec<i32>,
+    /// Puts the last picked item back in the list, with `transfer`.
+    pub put_back: Vec<i32>,
+    /// Hovers the next selected item in multiselect mode.
+    pub next_selected: Vec<i32>,
+    /// Hovers the previous selected item in multiselect mode.
+    pub prev_selected: Vec<i32>,
+    /// Computes the hovered item's preview, with `manual_preview`.
+    pub preview: Vec<i32>,
+}
+
+impl Default for Keys {
+    fn default() -> Keys {
+        Keys {
+            down: vec![KEY_DOWN, 'j' as i32],
+            up: vec![KEY_UP, 'k' as i32],
+            select: vec![10, KEY_ENTER],
+            multiselect: vec![32],
+            clipboard: Vec::new(),
+            search: vec!['/' as i32],
+            accept_selection: Vec::new(),
+            accept_current: Vec::new(),
+            clear_selection: vec![28], // Ctrl-\
+            expand: vec![KEY_RIGHT],
+            collapse: vec![KEY_LEFT],
+            undo: vec![26], // Ctrl-Z
+            redo: vec![25], // Ctrl-Y
+            quicklook: Vec::new(),
+            increment: vec!['+' as i32],
+            decrement: vec!['-' as i32],
+            put_back: vec![KEY_BACKSPACE, 127, 8],
+            next_selected: Vec::new(),
+            prev_selected: Vec::new(),
+            preview: vec![16], // Ctrl-P
+        }
+    }
+}
+
+impl Keys {
+    /// Every key bound to an action, whichever it is.
+    fn all(&self) -> impl Iterator<Item = i32> + '_ {
+        let keys = [
+            &self.down,
+            &self.up,
+            &self.select,
+            &self.multiselect,
+            &self.clipboard,
+            &self.search,
+            &self.accept_selection,
+            &self.accept_current,
+            &self.clear_selection,
+            &self.expand,
+            &self.collapse,
+            &self.undo,
+            &self.redo,
+            &self.quicklook,
+            &self.increment,
+            &self.decrement,
+            &self.put_back,
+            &self.next_selected,
+            &self.prev_selected,
+            &self.preview,
+        ];
+        // `keys.into_iter()` would borrow the array before the 2021 edition
+        IntoIterator::into_iter(keys).flat_map(|keys| keys.iter().copied())
+    }
+}
+
+/// The behavior of a menu. Each field corresponds to the builder method of
+/// the same name on `Menu`, and the defaults match those of `Menu::new`.
+///
+/// ```no_run
+/// let settings = youchoose::MenuSettings {
+///     multiselect: true,
+///     help: true,
+///     ..youchoose::MenuSettings::default()
+/// };
+///
+/// let first = youchoose::Menu::new(0..10).with_config(settings.clone()).show();
+/// let second = youchoose::Menu::new(10..20).with_config(settings).show();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct MenuSettings {
+    pub multiselect: bool,
+    /// Number of rows used in inline mode, or `None` to use the whole screen.
+    pub inline: Option<usize>,
+    pub hotkeys: bool,
+    /// Select the item on a row of the screen by pressing its number.
+    pub instant_select: bool,
+    pub help: bool,
+    pub icon_priority: IconPriority,
+    pub streaming: bool,
+    pub selection_order: Order,
+    pub borrow_curses: bool,
+    pub enter_selects_hover: bool,
+    /// Let the select key deselect the item selected last.
+    pub select_key_toggles_last: bool,
+    /// Ask to confirm the chosen items before returning them.
+    pub confirm: bool,
+    pub marker_position: MarkerPosition,
+    /// Glyphs of the unchecked and checked box drawn before each item in
+    /// multiselect mode, or `None` to show selection with the icon instead.
+    pub checkboxes: Option<(String, String)>,
+    /// How long the hover must rest on an item before its preview is computed.
+    pub preview_debounce: Option<Duration>,
+    pub search: bool,
+    /// Return the only item matching the search query as soon as the query
+    /// narrows the list down to it.
+    pub auto_accept_single: bool,
+    /// The tab separated field matched by the search query, or `None` to
+    /// match the whole item.
+    pub search_column: Option<usize>,
+    /// Shown when every item is filtered out, if set.
+    pub no_match_text: Option<String>,
+    /// Ignore accents when matching the search query. Requires the
+    /// `accent-folding` feature.
+    pub fold_accents: bool,
+    pub title_align: TextAlign,
+    pub title_style: ItemStyle,
+    /// Style of the borders drawn around panes and their labels.
+    pub box_style: ItemStyle,
+    pub scrollbar: bool,
+    /// Draw arrows when there are items above or below the screen.
+    pub scroll_indicators: bool,
+    /// Number of columns the items are indented by.
+    pub indent: u16,
+    /// Number of spaces between the icon and the item's text.
+    pub icon_gap: usize,
+    pub pin_selected: bool,
+    /// Sort the items by how well they match the search query.
+    pub rank_matches: bool,
+    /// Keep a log of every selection toggle.
+    pub record_history: bool,
+    /// Select every item when the menu is shown.
+    pub start_all_selected: bool,
+    /// Let items be chosen more than once, counting the choices.
+    pub quantities: bool,
+    /// Move chosen items out of the list, in the order they are picked.
+    pub transfer: bool,
+    /// Where to show the number of items passing the filters, if anywhere.
+    pub counter: Option<CounterPosition>,
+    /// Lay items out from the right edge, for right-to-left languages.
+    pub rtl: bool,
+    /// Display width each item is truncated to, if any.
+    pub max_item_width: Option<usize>,
+    /// How far the highlight of the hovered row extends past its text.
+    pub highlight_fill: HighlightFill,
+    /// Draw the icon column before the items.
+    pub show_icon: bool,
+    /// Move more rows at a time while a navigation key is held down.
+    pub key_acceleration: bool,
+    /// Where an item lands on the screen when the hover jumps to it, if
+    /// anywhere in particular.
+    pub jump_anchor: Option<JumpAnchor>,
+    /// Whether moving past the edge of the screen scrolls by a row or a page.
+    pub scroll_mode: ScrollMode,
+    /// Only redraw the rows that changed when the hover moves.
+    pub minimal_redraw: bool,
+    /// What to return when the menu is quit before anything was chosen.
+    pub default_on_quit: DefaultChoice,
+}
+
+impl Default for MenuSettings {
+    fn default() -> MenuSettings {
+        MenuSettings {
+            multiselect: false,
+            inline: None,
+            hotkeys: false,
+            instant_select: false,
+            help: false,
+            icon_priority: IconPriority::Chosen,
+            streaming: false,
+            selection_order: Order::AsToggled,
+            borrow_curses: false,
+            enter_selects_hover: false,
+            select_key_toggles_last: false,
+            marker_position: MarkerPosition::Prefix,
+            checkboxes: None,
+            preview_debounce: None,
+            search: false,
+            auto_accept_single: false,
+            search_column: None,
+            no_match_text: None,
+            fold_accents: false,
+            title_align: TextAlign::Left,
+            title_style: ItemStyle {
+                bold: true,
+                ..ItemStyle::default()
+            },
+            box_style: ItemStyle::default(),
+            counter: None,
+            rtl: false,
+            max_item_width: None,
+            highlight_fill: HighlightFill::None,
+            show_icon: true,
+            key_acceleration: false,
+            jump_anchor: None,
+            scroll_mode: ScrollMode::Line,
+            minimal_redraw: false,
+            default_on_quit: DefaultChoice::Empty,
+            scrollbar: false,
+            scroll_indicators: false,
+            indent: 0,
+            icon_gap: 1,
+            pin_selected: false,
+            rank_matches: false,
+            record_history: false,
+            start_all_selected: false,
+            quantities: false,
+            transfer: false,
+            confirm: false,
+        }
+    }
+}
+
+/// The parts of a menu that a tick callback can change, see `Menu::tick`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TickContext {
+    /// Number of ticks so far, starting at `1`.
+    pub ticks: u64,
+    /// The message in the status bar.
+    pub status: Option<String>,
+    /// The title shown above the items.
+    pub title: Option<String>,
+}
+
+/// Where the panes of a menu are placed, returned by `Menu::layout`. Each
+/// pane is given as `(top, left, bottom, right)`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// The pane with the items.
+    pub menu: (i32, i32, i32, i32),
+    /// The preview pane including its border, if there is one.
+    pub preview: Option<(i32, i32, i32, i32)>,
+}
+
+/// The hover position and selection of a menu, returned by
+/// `Menu::state_snapshot`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MenuSnapshot {
+    /// Row of the hovered item, counted from the first item on the screen.
+    pub hover: usize,
+    /// Position of the first item on the screen.
+    pub start: usize,
+    /// Original indices of the selected items, in the order they were
+    /// selected.
+    pub selection: Vec<usize>,
+}
+
+/// Something that happened in a menu, as yielded by `Menu::events`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MenuEvent {
+    /// A key was handled without moving the hover or changing the selection.
+    Key(i32),
+    /// The hover moved to the item with this original index.
+    Moved(usize),
+    /// Items were selected or deselected. Holds the chosen indices.
+    SelectionChanged(Vec<usize>),
+    /// The menu ended with these chosen indices.
+    Chosen(Vec<usize>),
+    /// The menu was quit with `ESC` or `q`. Holds what `show` would return.
+    Cancelled(Vec<usize>),
+}
+
+/// An error that prevented the menu from being shown.
+#[derive(Debug)]
+pub enum MenuError {
+    /// The terminal could not be initialized, e.g. because `TERM` is unset or
+    /// unknown.
+    Terminal,
+    /// Standard input or output is not a terminal, e.g. because it is piped.
+    NotATty,
+    /// `borrow_curses` was set, but curses has not been initialized.
+    NoHostCurses,
+    /// A preview setting was configured before calling `preview`. Holds the
+    /// name of the builder method.
+    NoPreview(&'static str),
+    /// A setting that reads every item was used with an infinite iterator.
+    /// Holds the name of the builder method.
+    Infinite(&'static str),
+    /// `inline` was given zero rows to draw the menu in.
+    NoRows,
+    /// Writing to or reading from the terminal failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for MenuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MenuError::Terminal => {
+                write!(f, "could not initialize the terminal")
+            }
+            MenuError::NotATty => {
+                write!(f, "standard input or output is not a terminal")
+            }
+            MenuError::NoHostCurses => {
+                write!(
+                    f,
+                    "borrow_curses is set, but curses is not initialized"
+                )
+            }
+            MenuError::NoPreview(method) => {
+                write!(f, "{} must be called after preview", method)
+            }
+            MenuError::Infinite(method) => {
+                write!(f, "{} needs a finite iterator", method)
+            }
+            MenuError::NoRows => write!(f, "inline needs at least one row"),
+            MenuError::Io(err) => write!(f, "terminal I/O failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MenuError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MenuError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MenuError {
+    fn from(err: io::Error) -> MenuError {
+        MenuError::Io(err)
+    }
+}
+
+/// Determines how keys are handled. Each mode can have its own keys, bound
+/// with `Menu::bind_mode_key`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// Navigating the items with the keys in `Keys`.
+    Normal,
+    /// Typing a search query.
+    Search,
+    /// A mode with only the keys bound to it, identified by its name.
+    Custom(&'static str),
+}
+
+/// Something a key can do, see `Menu::bind_mode_key`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Down,
+    Up,
+    Select,
+    Multiselect,
+    ClearSelection,
+    AcceptSelection,
+    AcceptCurrent,
+    Copy,
+    Quit,
+    SetMode(Mode),
+    /// Opens the hovered section.
+    Expand,
+    /// Closes the hovered section, or the one the hovered item is in.
+    Collapse,
+    /// Reverts the last selection toggle in multiselect mode.
+    Undo,
+    /// Reapplies the last undone selection toggle.
+    Redo,
+    /// Shows or hides the hovered item's preview over the menu.
+    QuickLook,
+    /// Chooses the hovered item once more, with `quantities`.
+    Increment,
+    /// Chooses the hovered item once less, with `quantities`.
+    Decrement,
+    /// Puts the last picked item back in the list, with `transfer`.
+    PutBack,
+    /// Hovers the next selected item, wrapping around.
+    NextSelected,
+    /// Hovers the previous selected item, wrapping around.
+    PrevSelected,
+    /// Computes the hovered item's preview, with `manual_preview`.
+    Preview,
+}
+
+/// The horizontal alignment of a line of text.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+/// Where the count of items passing the filters is shown.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CounterPosition {
+    /// At the end of the first line of the title.
+    Title,
+    /// Right aligned in the status bar.
+    Status,
+    /// In the label of the preview pane.
+    Preview,
+}
+
+/// Determines where the selected icon is drawn in multiselect mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MarkerPosition {
+    /// In place of the item's icon.
+    Prefix,
+    /// Right aligned at the end of the row, keeping the item's icon.
+    Suffix,
+}
+
+/// Determines the order of the indices returned in multiselect mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// The order in which the items were selected.
+    AsToggled,
+    /// The order in which the items appear in the menu.
+    ListOrder,
+}
+
+/// How far the highlight of the hovered row extends past its text.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HighlightFill {
+    /// Only the text is highlighted.
+    None,
+    /// The highlight is padded with spaces to the right edge of the pane.
+    Spaces,
+    /// The highlight is padded with spaces up to this column of the pane,
+    /// counted from its left edge.
+    ToColumn(usize),
+    /// The highlight is padded with this character to the right edge of the
+    /// pane, e.g. `'·'` for a dotted leader.
+    Char(char),
+}
+
+/// Where on the screen the hovered item is placed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JumpAnchor {
+    /// At the top row. Moving scrolls the list once the next row is the
+    /// last one on the screen.
+    Top,
+    /// A third of the way down.
+    OneThird,
+    /// In the middle.
+    Center,
+}
+
+/// How the list scrolls as the hover moves through it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScrollMode {
+    /// A row at a time, keeping the hover away from the edges.
+    Line,
+    /// A screen at a time, once the hover moves past the edge.
+    Page,
+}
+
+/// What a menu returns when it is quit before anything was chosen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DefaultChoice {
+    /// No indices.
+    Empty,
+    /// The hovered item, if there is one.
+    Hovered,
+    /// The first item passing the filters, if there is one.
+    First,
+    /// The given index, e.g. `usize::MAX` as a sentinel. Methods that
+    /// return the chosen items rather than their indices, such as
+    /// `show_with_meta`, leave it out unless an item has that index.
+    Index(usize),
+}
+
+/// Determines which icon is displayed for the hovered item when it is also
+/// selected.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IconPriority {
+    /// Show the selected icon.
+    Chosen,
+    /// Show the hover icon.
+    Hover,
+}
+
+struct Screen {
+    bounds: (Pair, Pair),
+    pos: Pair,
+    items_on_screen: usize,
+    side: ScreenSide,
+    width: f64,
+    // (first row, number of rows) of the terminal the screen is confined to
+    frame: Option<(i32, i32)>,
+    // rows at the (top, bottom) of the frame kept free for other elements
+    reserved: (i32, i32),
+    // columns at the right edge kept free, e.g. for the scrollbar
+    gutter: i32,
+    // columns at the left edge kept free
+    indent: i32,
+    // spaces between an item's icon and its text
+    icon_gap: i32,
+    // display width of the widest item icon
+    icon_width: i32,
+    // whether items are written leftwards from the right edge
+    rtl: bool,
+    // display width each line of an item is truncated to
+    max_item_width: Option<usize>,
+    // how far the highlight of the hovered row extends past its text
+    highlight_fill: HighlightFill,
+    // whether each row written by `addstr` is padded with `fill_highlight`
+    // before moving on to the next
+    fill_rows: bool,
+}
+
+impl Screen {
+    fn new(side: ScreenSide, width: f64) -> Screen {
+        assert!(width > 0.0 && width <= 1.0);
+
+        let bounds = (Pair { y: 0, x: 0 }, Pair { y: 0, x: 0 });
+        let pos = Pair { y: 0, x: 0 };
+
+        Screen {
+            bounds,
+            pos,
+            items_on_screen: 0,
+            side,
+            width,
+            frame: None,
+            reserved: (0, 0),
+            gutter: 0,
+            indent: 0,
+            icon_gap: 1,
+            icon_width: 1,
+            rtl: false,
+            max_item_width: None,
+            highlight_fill: HighlightFill::None,
+            fill_rows: false,
+        }
+    }
+
+    fn show(&mut self) {
+        self.layout();
+    }
+
+    fn layout(&mut self) {
+        self.bounds = self.bounds_in(&Self::get_size());
+    }
+
+    /// The bounds of the screen in a terminal of the given size.
+    fn bounds_in(&self, size: &Pair) -> (Pair, Pair) {
+        let mut bounds = self.side_bounds(self.root_bounds_in(size));
+        // The gap left after the first pane can push a tiny second one past
+        // the edge of the terminal
+        bounds.0.x = bounds.0.x.min(bounds.1.x);
+        bounds.0.y = bounds.0.y.min(bounds.1.y);
+        if self.side == ScreenSide::Center {
+            // Leave room for the border
+            bounds.0.x = (bounds.0.x + 1).min(bounds.1.x);
+            bounds.1.x = (bounds.1.x - 1).max(bounds.0.x);
+        }
+        bounds.1.x = (bounds.1.x - self.gutter).max(bounds.0.x);
+        bounds.0.x = (bounds.0.x + self.indent).min(bounds.1.x);
+        bounds
+    }
+
+    fn too_small(&self, min_height: i32, min_width: i32) -> bool {
+        self.bounds.1.y - self.bounds.0.y < min_height
+            || self.bounds.1.x - self.bounds.0.x < min_width
+    }
+
+    /// Whether there is room for an item `rows` rows tall on the next line.
+    /// The first item on the screen is let in even if it doesn't fit, to be
+    /// cut off at the bottom edge.
+    fn has_room(&self, rows: i32) -> bool {
+        let y = self.pos.y + 1;
+        y + rows.max(1) < self.bounds.1.y
+            || (self.items_on_screen == 0 && y + 1 < self.bounds.1.y)
+    }
+
+    /// Writes the item on the next line.
+    fn write_item(
+        &mut self,
+        item: &Item,
+        icon: Option<&str>,
+        marker: Option<&str>,
+        highlight: bool,
+        pinned: bool,
+        hotkey: Option<char>,
+    ) {
+        self.skiplines(1);
+
+        let icon_color = if item.chosen() && marker.is_none() {
+            3
+        } else {
+            2
+        };
+        let first_row = self.pos.y;
+
+        if self.rtl {
+            self.pos.x = self.bounds.1.x;
+        }
+        if let Some(icon) = icon {
+            attron(COLOR_PAIR(icon_color));
+            attron(A_BOLD());
+
+            // Pad the icon so the text lines up across rows, whatever the
+            // display width of each icon
+            let icon_x = self.pos.x;
+            self.put(icon);
+            self.pos.x = if self.rtl {
+                icon_x - self.icon_width
+            } else {
+                icon_x + self.icon_width
+            };
+            for _ in 0..self.icon_gap {
+                self.putch(' ');
+            }
+
+            attroff(A_BOLD());
+            attroff(COLOR_PAIR(icon_color));
+        }
+
+        if let Some(c) = hotkey {
+            attron(A_BOLD());
+            self.putch(c);
+            self.putch(' ');
+            attroff(A_BOLD());
+        }
+
+        self.put(&item.section_prefix());
+
+        if highlight {
+            attron(COLOR_PAIR(1));
+        }
+        if pinned {
+            attron(A_BOLD());
+        }
+
+        let text_x = self.pos.x;
+        let count = item.count_suffix();
+        let mut max = self.max_item_width;
+        if let Some(marker) = marker {
+            let room = if self.rtl {
+                text_x - self.bounds.0.x
+            } else {
+                self.bounds.1.x - text_x
+            };
+            let room = marker_room(room.max(0) as usize, marker, &count);
+            max = Some(max.map_or(room, |max| max.min(room)));
+        }
+        // Every row of a wrapped or multiline item is highlighted alike
+        self.fill_rows = highlight && !self.rtl;
+        match max {
+            Some(max) => {
+                let lines: Vec<String> = item
+                    .string()
+                    .split('\n')
+                    .map(|line| truncate(line, max))
+                    .collect();
+                self.put(&lines.join("\n"));
+            }
+            None => self.put(item.string()),
+        }
+        self.fill_rows = false;
+        if highlight && !self.rtl {
+            self.fill_highlight();
+        }
+
+        if pinned {
+            attroff(A_BOLD());
+        }
+        if highlight {
+            attroff(COLOR_PAIR(1));
+        }
+        self.put(&count);
+
+        if let Some(marker) = marker {
+            // Right align the marker so it lines up across rows
+            let width = UnicodeWidthStr::width(marker) as i32;
+            let x = if self.rtl {
+                self.bounds.0.x + 1
+            } else {
+                self.bounds.1.x - width - 1
+            };
+            attron(COLOR_PAIR(3));
+            attron(A_BOLD());
+            mvaddstr(first_row, x, marker);
+            attroff(A_BOLD());
+            attroff(COLOR_PAIR(3));
+        }
+
+        if let Some(desc) = &item.desc {
+            // Line the description up with the item's text
+            self.skiplines(1);
+            self.pos.x = text_x;
+            attron(A_DIM());
+            self.put(desc);
+            attroff(A_DIM());
+        }
+
+        self.items_on_screen += 1;
+    }
+
+    /// Fills the rows from `first` up to `end` of the pane with spaces.
+    fn blank(&self, first: i32, end: i32) {
+        let width = (self.bounds.1.x - self.bounds.0.x).max(0) as usize;
+        for y in first..end.min(self.bounds.1.y) {
+            mvaddstr(y, self.bounds.0.x, &" ".repeat(width));
+        }
+    }
+
+    /// Writes a dimmed line that isn't an item on the next line. Returns
+    /// whether there was room for it and an item after it.
+    fn write_header(&mut self, text: &str) -> bool {
+        self.skiplines(1);
+        if self.pos.y + 1 >= self.bounds.1.y {
+            return false;
+        }
+
+        attron(A_DIM());
+        self.addstr(text);
+        attroff(A_DIM());
+        true
+    }
+
+    /// Writes a loading indicator on the next line, if there is room for it.
+    fn write_loading(&mut self, frame: &str) {
+        self.skiplines(1);
+        if self.pos.y >= self.bounds.1.y - 1 {
+            return;
+        }
+
+        attron(A_DIM());
+        self.addstr(frame);
+        self.addstr(" loading");
+        attroff(A_DIM());
+    }
+
+    /// Extends the highlight of the hovered row past its text, as set with
+    /// `highlight_fill`.
+    fn fill_highlight(&mut self) {
+        let (end, glyph) = match self.highlight_fill {
+            HighlightFill::None => return,
+            HighlightFill::Spaces => (self.bounds.1.x, ' '),
+            HighlightFill::ToColumn(column) => {
+                ((self.bounds.0.x + column as i32).min(self.bounds.1.x), ' ')
+            }
+            HighlightFill::Char(c) => (self.bounds.1.x, c),
+        };
+        if self.pos.x < end {
+            let padding = fill_padding(glyph, (end - self.pos.x) as usize);
+            self.addstr_clean(&padding);
+        }
+    }
+
+    /// Writes a dimmed line in the middle of the pane.
+    fn write_centered(&mut self, text: &str) {
+        let width = (self.bounds.1.x - self.bounds.0.x).max(0) as usize;
+        let mut line = String::new();
+        for c in text.chars() {
+            line.push(c);
+            if UnicodeWidthStr::width(line.as_str()) > width {
+                line.pop();
+                break;
+            }
+        }
+        let margin = (width - UnicodeWidthStr::width(line.as_str())) / 2;
+
+        attron(A_DIM());
+        mvaddstr(
+            (self.bounds.0.y + self.bounds.1.y) / 2,
+            self.bounds.0.x + margin as i32,
+            &line,
+        );
+        attroff(A_DIM());
+    }
+
+    fn draw_box(
+        &mut self,
+        side: ScreenSide,
+        width: f64,
+        label: &Option<String>,
+    ) {
+        let bounds = side
+            .get_bounds((self.bounds.0.clone(), self.bounds.1.clone()), width);
+
+        let box_width = (bounds.1.x - bounds.0.x).max(0) as usize;
+        let box_height = (bounds.1.y - bounds.0.y).max(0) as usize;
+
+        // Not even room for the corners
+        if box_width < 2 || box_height < 2 {
+            return;
+        }
+
+        let hor_line = "─";
+        let vert_line = "│";
+        let corner_tl = "┌";
+        let corner_bl = "└";
+        let corner_tr = "┐";
+        let corner_br = "┘";
+
+        // top line
+        self.pos.x = bounds.0.x;
+        self.pos.y = bounds.0.y;
+        self.addstr(corner_tl);
+        let label = label.as_deref().unwrap_or(" preview ");
+        let label_len = label.chars().count();
+        // Leave the label out if it doesn't fit between the corners
+        let line_len = if label_len + 2 <= box_width {
+            self.addstr(label);
+            box_width - label_len - 2
+        } else {
+            box_width - 2
+        };
+        self.addstr(&hor_line.repeat(line_len));
+        self.addstr(corner_tr);
+
+        // vertical lines
+        // accessing curses directly
+        for row in bounds.0.y + 1..bounds.1.y {
+            mvaddstr(row, bounds.0.x, vert_line);
+            mvaddstr(row, bounds.1.x - 1, vert_line);
+        }
+
+        // bottom line
+        self.pos.x = bounds.0.x;
+        self.pos.y = bounds.1.y - 1;
+        self.addstr(corner_bl);
+        self.addstr(&hor_line.to_string().repeat(box_width - 2));
+        self.addstr(corner_br);
+    }
+
+    fn get_key(&self) -> i32 {
+        getch()
+    }
+
+    fn refresh(&mut self) {
+        refresh();
+        self.layout();
+    }
+
+    fn erase(&mut self) {
+        erase();
+        self.layout();
+    }
+
+    fn max_y(&self) -> usize {
+        self.bounds.1.y.max(0) as usize
+    }
+
+    fn _max_x(&mut self) -> usize {
+        self.bounds.1.x as usize
+    }
+
+    fn reset_pos(&mut self) {
+        self.pos.y = self.bounds.0.y;
+        self.pos.x = self.bounds.0.x;
+        self.items_on_screen = 0;
+    }
+
+    fn get_size() -> Pair {
+        let mut size = Pair { y: 0, x: 0 };
+        getmaxyx(stdscr(), &mut size.y, &mut size.x);
+        size
+    }
+
+    /// The bounds of the screen's side of `root`. The sides are laid out
+    /// from the top of the terminal, so `root` is moved up there and back.
+    fn side_bounds(&self, root: (Pair, Pair)) -> (Pair, Pair) {
+        let top = root.0.y;
+        let origin = (
+            Pair { y: 0, x: root.0.x },
+            Pair {
+                y: root.1.y - top,
+                x: root.1.x,
+            },
+        );
+        let (mut top_left, mut bottom_right) =
+            self.side.get_bounds(origin, self.width);
+        top_left.y += top;
+        bottom_right.y += top;
+        (top_left, bottom_right)
+    }
+
+    /// The region of the terminal this screen is laid out in.
+    fn root_bounds(&self) -> (Pair, Pair) {
+        self.root_bounds_in(&Self::get_size())
+    }
+
+    fn root_bounds_in(&self, size: &Pair) -> (Pair, Pair) {
+        let (top_left, bottom_right) = self.frame_bounds_in(size);
+        // Don't let the reserved rows push the edges past each other
+        let top = (top_left.y + self.reserved.0).min(bottom_right.y);
+        let bottom = (bottom_right.y - self.reserved.1).max(top);
+        (
+            Pair {
+                y: top,
+                x: top_left.x,
+            },
+            Pair {
+                y: bottom,
+                x: bottom_right.x,
+            },
+        )
+    }
+
+    /// The region of the terminal available to the menu, including the
+    /// reserved rows.
+    fn frame_bounds(&self) -> (Pair, Pair) {
+        self.frame_bounds_in(&Self::get_size())
+    }
+
+    fn frame_bounds_in(&self, size: &Pair) -> (Pair, Pair) {
+        match self.frame {
+            Some((top, rows)) => {
+                let top = top.min(size.y - 1).max(0);
+                (
+                    Pair { y: top, x: 0 },
+                    Pair {
+                        y: (top + rows).min(size.y),
+                        x: size.x,
+                    },
+                )
+            }
+            None => (Pair { y: 0, x: 0 }, size.clone()),
+        }
+    }
+
+    fn set_frame(&mut self, frame: Option<(i32, i32)>) {
+        self.frame = frame;
+    }
+
+    fn set_reserved(&mut self, reserved: (i32, i32)) {
+        self.reserved = reserved;
+    }
+
+    /// Writes the string from the current position, wrapping it at the right
+    /// edge and at newlines, and dropping whatever falls below the bottom
+    /// edge.
+    fn addstr(&mut self, s: &str) {
+        if self.pos.y >= self.bounds.1.y {
+            return;
+        }
+        let width = (self.bounds.1.x - self.bounds.0.x).max(0) as usize;
+        // Continue from the current column so segments wrap correctly
+        let column = (self.pos.x - self.bounds.0.x).max(0) as usize;
+        for (row, column, line) in wrap_text(s, column, width) {
+            if row > 0 {
+                if self.fill_rows {
+                    self.fill_highlight();
+                }
+                self.pos.y += 1;
+                if self.pos.y >= self.bounds.1.y {
+                    return;
+                }
+            }
+            self.pos.x = self.bounds.0.x + column as i32;
+            self.addstr_clean(&line);
+        }
+    }
+
+    /// Writes the string in the direction of the text.
+    fn put(&mut self, s: &str) {
+        if self.rtl {
+            self.addstr_rtl(s);
+        } else {
+            self.addstr(s);
+        }
+    }
+
+    fn putch(&mut self, c: char) {
+        if self.rtl {
+            self.addstr_rtl(&c.to_string());
+        } else {
+            self.addch(c);
+        }
+    }
+
+    /// Writes the first line of the string leftwards from the current
+    /// position, so that it reads from right to left. Stops at the left edge
+    /// instead of wrapping.
+    fn addstr_rtl(&mut self, s: &str) {
+        let line = s.split('\n').next().unwrap_or("");
+        // Keep combining marks with the character they belong to
+        let mut clusters: Vec<String> = Vec::new();
+        for c in line.chars() {
+            match clusters.last_mut() {
+                Some(last) if UnicodeWidthChar::width(c) == Some(0) => {
+                    last.push(c)
+                }
+                _ => clusters.push(c.to_string()),
+            }
+        }
+        for cluster in clusters {
+            let width = UnicodeWidthStr::width(cluster.as_str()) as i32;
+            if self.pos.x - width < self.bounds.0.x {
+                break;
+            }
+            self.pos.x -= width;
+            mvaddstr(self.pos.y, self.pos.x, &cluster);
+        }
+    }
+
+    fn addstr_styled(&mut self, text: &[(String, ItemStyle)]) {
+        for (s, style) in text {
+            style.on();
+            self.addstr(s);
+            style.off();
+        }
+    }
+
+    fn addstr_clean(&mut self, s: &str) {
+        mvaddstr(self.pos.y, self.pos.x, s);
+        self.pos.x += UnicodeWidthStr::width(s) as i32;
+    }
+
+    fn addch(&mut self, c: char) {
+        mvaddch(self.pos.y, self.pos.x, c as u32);
+        self.pos.x += 1;
+    }
+
+    fn skiplines(&mut self, n: i32) {
+        self.pos.y += n;
+        self.pos.x = self.bounds.0.x;
+    }
+
+    fn set_pos(&mut self, new_side: ScreenSide, width: f64) {
+        assert!(width > 0.0 && width <= 1.0);
+
+        self.side = new_side;
+        self.width = width;
+    }
+}
+
+/// A preview being computed on a worker thread, with `preview_threaded`.
+struct PendingPreview {
+    receiver: Receiver<StyledText>,
+    since: Instant,
+    // whether the worker stopped without sending a preview
+    failed: bool,
+}
+
+struct Item<'a> {
+    // position of the item in the original iterable
+    index: usize,
+    icon: &'a str,
+    chosen_icon: &'a str,
+    chosen: bool,
+    repr: String,
+    // text matched against the search query, if it is not `repr`
+    search_repr: Option<String>,
+    // secondary text drawn below `repr`
+    desc: Option<String>,
+    // result of each of the menu's filters for this item
+    filter_hits: Vec<bool>,
+    preview: Option<StyledText>,
+    // the preview while a worker thread is still computing it
+    pending: Option<PendingPreview>,
+    // nesting level, position in `items` of the section header this item is
+    // in, and whether this item heads a section and it is closed
+    depth: usize,
+    parent: Option<usize>,
+    has_children: bool,
+    collapsed: bool,
+    // how many times the item is chosen, with `quantities`
+    count: u32,
+}
+
+impl<'a> Item<'a> {
+    fn new(
+        thing: &impl fmt::Display,
+        index: usize,
+        icon: &'a str,
+        chosen_icon: &'a str,
+    ) -> Item<'a> {
+        Item {
+            index,
+            icon,
+            chosen_icon,
+            chosen: false,
+            repr: thing.to_string(),
+            search_repr: None,
+            desc: None,
+            filter_hits: Vec::new(),
+            preview: None,
+            pending: None,
+            depth: 0,
+            parent: None,
+            has_children: false,
+            collapsed: false,
+            count: 1,
+        }
+    }
+
+    /// Indentation and open/closed marker of the item's section.
+    fn section_prefix(&self) -> String {
+        let marker = match (self.has_children, self.collapsed) {
+            (false, _) => "",
+            (true, false) => "▾ ",
+            (true, true) => "▸ ",
+        };
+        format!("{}{}", "  ".repeat(self.depth), marker)
+    }
+
+    fn select(&mut self) {
+        self.chosen = !self.chosen;
+        self.count = 1;
+    }
+
+    fn chosen(&self) -> bool {
+        self.chosen
+    }
+
+    /// How many times the item is chosen, drawn after its text, if more
+    /// than once.
+    fn count_suffix(&self) -> String {
+        if self.chosen && self.count > 1 {
+            format!(" (x{})", self.count)
+        } else {
+            String::new()
+        }
+    }
+
+    fn icon(&self) -> &str {
+        if self.chosen {
+            self.chosen_icon
+        } else {
+            self.icon
+        }
+    }
+
+    fn string(&self) -> &String {
+        &self.repr
+    }
+
+    fn search_text(&self) -> &str {
+        self.search_repr.as_deref().unwrap_or(&self.repr)
+    }
+}
+
+impl<'a> fmt::Display for Item<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.icon(), self.repr)
+    }
+}
+
+struct Pair {
+    y: i32,
+    x: i32,
+}
+
+impl Pair {
+    fn clone(&self) -> Pair {
+        Pair {
+            y: self.y,
+            x: self.x,
+        }
+    }
+}
+
+impl fmt::Display for Pair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pair({}, {})", self.y, self.x)
+    }
+}
+
+/// Determines the side on which a pane should be located.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScreenSide {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    /// This option is not affected by width. It will always fill the screen.
+    Full,
+    /// A box in the middle of the screen, with the width applying to both
+    /// its width and height. As the preview's side, it is treated like
+    /// `Full`.
+    Center,
+}
 
 impl ScreenSide {
     fn get_bounds(
@@ -796,6 +6459,22 @@ impl ScreenSide {
                 screen_bounds.1.clone(),
             ),
             Self::Full => screen_bounds,
+            Self::Center => {
+                let (top_left, bottom_right) = screen_bounds;
+                let height = bottom_right.y - top_left.y;
+                let columns = bottom_right.x - top_left.x;
+                let box_height = ((height as f64) * width) as i32;
+                let box_width = ((columns as f64) * width) as i32;
+                let top = top_left.y + (height - box_height) / 2;
+                let left = top_left.x + (columns - box_width) / 2;
+                (
+                    Pair { y: top, x: left },
+                    Pair {
+                        y: top + box_height,
+                        x: left + box_width,
+                    },
+                )
+            }
         }
     }
 }
@@ -808,30 +6487,172 @@ impl ops::Not for ScreenSide {
             Self::Bottom => Self::Top,
             Self::Left => Self::Right,
             Self::Right => Self::Left,
-            Self::Full => Self::Full,
+            Self::Full | Self::Center => Self::Full,
+        }
+    }
+}
+
+/// Text made up of segments that are drawn with different styles.
+pub type StyledText = Vec<(String, ItemStyle)>;
+
+/// The attributes used to draw a piece of text. The default style uses the
+/// terminal's colors and no attributes.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ItemStyle {
+    /// Foreground color. `None` uses the terminal's default.
+    pub fg: Option<Color>,
+    /// Background color. `None` uses the terminal's default.
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl ItemStyle {
+    fn attrs(&self) -> attr_t {
+        let mut attrs = A_NORMAL();
+        if self.fg.is_some() || self.bg.is_some() {
+            attrs |= COLOR_PAIR(Color::pair(self.fg, self.bg));
+        }
+        if self.bold {
+            attrs |= A_BOLD();
+        }
+        if self.dim {
+            attrs |= A_DIM();
+        }
+        if self.underline {
+            attrs |= A_UNDERLINE();
+        }
+        if self.reverse {
+            attrs |= A_REVERSE();
+        }
+        attrs
+    }
+
+    fn on(&self) {
+        attron(self.attrs());
+    }
+
+    fn off(&self) {
+        attroff(self.attrs());
+    }
+}
+
+/// One of the 8 basic terminal colors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+impl Color {
+    fn code(color: Option<Color>) -> i16 {
+        match color {
+            Some(Color::Black) => COLOR_BLACK,
+            Some(Color::Red) => COLOR_RED,
+            Some(Color::Green) => COLOR_GREEN,
+            Some(Color::Yellow) => COLOR_YELLOW,
+            Some(Color::Blue) => COLOR_BLUE,
+            Some(Color::Magenta) => COLOR_MAGENTA,
+            Some(Color::Cyan) => COLOR_CYAN,
+            Some(Color::White) => COLOR_WHITE,
+            // -1 means default color
+            None => -1,
         }
     }
+
+    /// The color pair for the given foreground and background, initializing
+    /// it the first time it is used. Pairs below `STYLE_PAIR_OFFSET` are used
+    /// by the menu. Falls back to pair 0, the terminal's colors, once the
+    /// terminal has no pairs left.
+    fn pair(fg: Option<Color>, bg: Option<Color>) -> i16 {
+        let colors = (Self::code(fg), Self::code(bg));
+        STYLE_PAIR_COLORS.with(|pairs| {
+            let mut pairs = pairs.borrow_mut();
+            if let Some(i) = pairs.iter().position(|&c| c == colors) {
+                return STYLE_PAIR_OFFSET + i as i16;
+            }
+            let pair = STYLE_PAIR_OFFSET + pairs.len() as i16;
+            if i32::from(pair) >= COLOR_PAIRS() {
+                return 0;
+            }
+            init_pair(pair, colors.0, colors.1);
+            pairs.push(colors);
+            pair
+        })
+    }
 }
 
 struct DispFunc<D>
 where
     D: fmt::Display,
 {
-    func: Box<dyn Fn(D) -> String>,
+    func: Box<dyn Fn(D) -> PreviewJob>,
+    // how long a threaded preview may take before it is shown as timed out
+    timeout: Option<Duration>,
 }
 
 impl<D> DispFunc<D>
 where
     D: fmt::Display,
 {
-    fn new(func: Box<dyn Fn(D) -> String>) -> DispFunc<D> {
-        DispFunc { func }
+    fn new<F>(func: F) -> DispFunc<D>
+    where
+        F: Fn(D) -> StyledText + 'static,
+    {
+        DispFunc {
+            func: Box::new(move |param| PreviewJob::Ready(func(param))),
+            timeout: None,
+        }
     }
-    fn eval(&self, param: D) -> String {
+    /// Wraps a function that starts computing on a worker thread and returns
+    /// the channel the result will be sent on.
+    fn threaded<F>(func: F) -> DispFunc<D>
+    where
+        F: Fn(D) -> Receiver<StyledText> + 'static,
+    {
+        DispFunc {
+            func: Box::new(move |param| PreviewJob::Running(func(param))),
+            timeout: None,
+        }
+    }
+    fn eval(&self, param: D) -> PreviewJob {
         (*self.func)(param)
     }
 }
 
+/// A computed preview, or the channel a worker thread will send it on.
+enum PreviewJob {
+    Ready(StyledText),
+    Running(Receiver<StyledText>),
+}
+
+type CmpFunc<D> = Box<dyn Fn(&D, &D) -> Ordering>;
+type DescFunc<D> = Box<dyn Fn(&D) -> Option<String>>;
+type SearchFunc<D> = Box<dyn Fn(&D) -> String>;
+type MatchFunc = Box<dyn Fn(&str, &str) -> Option<i32>>;
+type CloseFunc = Box<dyn Fn(&[usize])>;
+type ActionFunc = Box<dyn Fn(&str)>;
+type SubmenuFunc = Box<dyn Fn() -> Result<Vec<usize>, MenuError>>;
+type DepthFunc<D> = Box<dyn Fn(&D) -> usize>;
+type TickFunc = Box<dyn FnMut(&mut TickContext)>;
+type PollFunc<I, D> = fn(&mut I) -> Result<D, TryRecvError>;
+#[cfg(feature = "disk-cache")]
+type KeyFunc<D> = Box<dyn Fn(&D) -> String>;
+
+struct Filter<D> {
+    key: i32,
+    pred: Box<dyn Fn(&D) -> bool>,
+    active: bool,
+}
+
 struct Preview<D>
 where
     D: fmt::Display,
@@ -840,6 +6661,145 @@ where
     box_screen: Screen,
     screen: Screen,
     label: Option<String>,
+    // whether previews are escape sequences passed on to the terminal
+    raw: bool,
+    // whether previews are only computed when the preview key is pressed
+    manual: bool,
+    // glyph and style of the line between the menu and the preview
+    divider: Option<(char, ItemStyle)>,
+    // rows and columns between the box and its contents, from the top going
+    // clockwise, including the border
+    padding: (i32, i32, i32, i32),
+    #[cfg(feature = "disk-cache")]
+    cache: Option<DiskCache<D>>,
+}
+
+/// Previews stored on disk, keyed by a hash of the version tag and the
+/// item's display string or cache key.
+#[cfg(feature = "disk-cache")]
+struct DiskCache<D> {
+    dir: std::path::PathBuf,
+    version: String,
+    key: Option<KeyFunc<D>>,
+}
+
+#[cfg(feature = "disk-cache")]
+impl<D> DiskCache<D>
+where
+    D: fmt::Display,
+{
+    fn eval(&self, value: D, repr: &str, func: &DispFunc<D>) -> PreviewJob {
+        let key = match &self.key {
+            Some(key) => key(&value),
+            None => repr.to_string(),
+        };
+        let path = self.dir.join(format!("{:016x}.preview", self.hash(&key)));
+        if let Some(text) = std::fs::read(&path).ok().and_then(decode_preview)
+        {
+            return PreviewJob::Ready(text);
+        }
+
+        let dir = self.dir.clone();
+        let store = move |text: &StyledText| {
+            // The cache is only an optimization, so failing to write it is
+            // fine
+            let _ = std::fs::create_dir_all(&dir)
+                .and_then(|()| std::fs::write(&path, encode_preview(text)));
+        };
+        match func.eval(value) {
+            PreviewJob::Ready(text) => {
+                store(&text);
+                PreviewJob::Ready(text)
+            }
+            PreviewJob::Running(receiver) => {
+                // Only previews that arrive are stored, not failed ones
+                let (sender, forward) = mpsc::channel();
+                thread::spawn(move || {
+                    if let Ok(text) = receiver.recv() {
+                        store(&text);
+                        let _ = sender.send(text);
+                    }
+                });
+                PreviewJob::Running(forward)
+            }
+        }
+    }
+
+    /// FNV-1a, which unlike the std hasher is stable across Rust versions.
+    fn hash(&self, key: &str) -> u64 {
+        let bytes = self.version.bytes().chain(Some(0)).chain(key.bytes());
+        bytes.fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+}
+
+/// Each segment is stored as a `fg bg flags length` header line followed by
+/// its text. Colors are curses color codes, with `-1` for the default.
+#[cfg(feature = "disk-cache")]
+fn encode_preview(text: &[(String, ItemStyle)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (segment, style) in text {
+        let flags = [style.bold, style.dim, style.underline, style.reverse]
+            .iter()
+            .enumerate()
+            .fold(0, |flags, (bit, &on)| flags | ((on as u8) << bit));
+        let header = format!(
+            "{} {} {} {}\n",
+            Color::code(style.fg),
+            Color::code(style.bg),
+            flags,
+            segment.len()
+        );
+        out.extend_from_slice(header.as_bytes());
+        out.extend_from_slice(segment.as_bytes());
+    }
+    out
+}
+
+#[cfg(feature = "disk-cache")]
+fn decode_preview(bytes: Vec<u8>) -> Option<StyledText> {
+    const COLORS: [Color; 8] = [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::White,
+    ];
+    let color = |code: &str| -> Option<Option<Color>> {
+        match code.parse::<i16>().ok()? {
+            -1 => Some(None),
+            code => COLORS.get(code as usize).copied().map(Some),
+        }
+    };
+
+    let mut text = Vec::new();
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        let newline = rest.iter().position(|&b| b == b'\n')?;
+        let header = std::str::from_utf8(&rest[..newline]).ok()?;
+        let fields: Vec<&str> = header.split(' ').collect();
+        if fields.len() != 4 {
+            return None;
+        }
+        let flags: u8 = fields[2].parse().ok()?;
+        let len: usize = fields[3].parse().ok()?;
+        let body = rest.get(newline + 1..newline + 1 + len)?;
+        let style = ItemStyle {
+            fg: color(fields[0])?,
+            bg: color(fields[1])?,
+            bold: flags & 1 != 0,
+            dim: flags & 2 != 0,
+            underline: flags & 4 != 0,
+            reverse: flags & 8 != 0,
+        };
+        text.push((String::from_utf8(body.to_vec()).ok()?, style));
+        rest = &rest[newline + 1 + len..];
+    }
+    Some(text)
 }
 
 impl<D> Preview<D>
@@ -855,11 +6815,86 @@ where
             box_screen,
             screen,
             label: None,
+            raw: false,
+            manual: false,
+            divider: None,
+            padding: (1, 1, 1, 1),
+            #[cfg(feature = "disk-cache")]
+            cache: None,
+        }
+    }
+
+    /// Whether the padding leaves room for a border on every side.
+    fn has_border(&self) -> bool {
+        let (top, right, bottom, left) = self.padding;
+        top > 0 && right > 0 && bottom > 0 && left > 0
+    }
+
+    /// Computes the preview of an item, going through the disk cache if
+    /// there is one.
+    fn eval(&self, value: D, repr: &str) -> PreviewJob {
+        #[cfg(feature = "disk-cache")]
+        {
+            if let Some(cache) = &self.cache {
+                return cache.eval(value, repr, &self.func);
+            }
+        }
+        let _ = repr;
+        self.func.eval(value)
+    }
+
+    /// Draws the box, with `counter` appended to the label if given.
+    fn draw_box(&mut self, counter: Option<&str>) {
+        let label = match counter {
+            Some(counter) => Some(format!(
+                "{}{} ",
+                self.label.as_deref().unwrap_or(" preview "),
+                counter
+            )),
+            None => self.label.clone(),
+        };
+        if self.has_border() {
+            self.box_screen.draw_box(ScreenSide::Full, 1.0, &label);
         }
+        self.draw_divider();
     }
 
-    fn draw_box(&mut self) {
-        self.box_screen.draw_box(ScreenSide::Full, 1.0, &self.label);
+    /// Draws the divider in the gap between the box and the menu.
+    fn draw_divider(&self) {
+        let (glyph, style) = match self.divider {
+            Some(divider) => divider,
+            None => return,
+        };
+        let (top_left, bottom_right) = &self.box_screen.bounds;
+        let size = Screen::get_size();
+        let glyph = glyph.to_string();
+
+        style.on();
+        match self.box_screen.side {
+            ScreenSide::Left | ScreenSide::Right => {
+                let x = match self.box_screen.side {
+                    ScreenSide::Left => bottom_right.x,
+                    _ => top_left.x - 1,
+                };
+                if x >= 0 && x < size.x {
+                    for y in top_left.y..bottom_right.y {
+                        mvaddstr(y, x, &glyph);
+                    }
+                }
+            }
+            ScreenSide::Top | ScreenSide::Bottom => {
+                let y = match self.box_screen.side {
+                    ScreenSide::Top => bottom_right.y,
+                    _ => top_left.y - 1,
+                };
+                let width = (bottom_right.x - top_left.x).max(0) as usize;
+                if y >= 0 && y < size.y {
+                    mvaddstr(y, top_left.x, &glyph.repeat(width));
+                }
+            }
+            ScreenSide::Full | ScreenSide::Center => (),
+        }
+        style.off();
     }
 
     fn show(&mut self) {
@@ -869,10 +6904,18 @@ where
     }
 
     fn update_bounds(&mut self) {
-        self.screen.bounds.0.y += 1;
-        self.screen.bounds.0.x += 1;
-        self.screen.bounds.1.y -= 1;
-        self.screen.bounds.1.x -= 1;
+        let (top, right, bottom, left) = self.padding;
+        let bounds = &mut self.screen.bounds;
+        bounds.0.y += top;
+        bounds.0.x += left;
+        bounds.1.y = (bounds.1.y - bottom).max(bounds.0.y);
+        bounds.1.x = (bounds.1.x - right).max(bounds.0.x);
+    }
+
+    fn layout(&mut self) {
+        self.screen.layout();
+        self.box_screen.layout();
+        self.update_bounds();
     }
 
     fn refresh(&mut self) {
@@ -890,43 +6933,1130 @@ where
     fn set_label(&mut self, label: String) {
         self.label = Some(label);
     }
+
+    fn set_frame(&mut self, frame: Option<(i32, i32)>) {
+        self.screen.set_frame(frame);
+        self.box_screen.set_frame(frame);
+    }
+
+    fn set_reserved(&mut self, reserved: (i32, i32)) {
+        self.screen.set_reserved(reserved);
+        self.box_screen.set_reserved(reserved);
+    }
 }
 
-// fn log(s: &str) -> std::io::Result<()> {
-//     let mut file = OpenOptions::new()
-//         .write(true)
-//         .append(true)
-//         .open("choose.log")
-//         .unwrap();
-//     writeln!(file, "{}", s)?;
-//     Ok(())
-// }
+/// The curses session a menu is shown in, and the part of the terminal it
+/// may draw in.
+struct Curses {
+    host: Option<HostCurses>,
+    session: Option<Session>,
+    frame: Option<(i32, i32)>,
+}
+
+impl Curses {
+    /// Hands the terminal back to the host, or ends the session.
+    fn close(self) {
+        if let Some(host) = self.host {
+            return_curses(host);
+        }
+        if let Some(session) = self.session {
+            end_curses(session);
+        }
+        if let Some((top, rows)) = self.frame {
+            // Take the menu off the rows it was drawn on and leave the cursor
+            // where it started, as `endwin` moves it to the bottom
+            let _ = blank_rows(top, rows);
+        }
+    }
+}
+
+/// The curses screen created for the menu, and the streams it draws to and
+/// reads from.
+struct Session {
+    screen: SCREEN,
+    output: *mut libc::FILE,
+    input: *mut libc::FILE,
+}
+
+/// Opens a stream on a copy of `fd`, so that closing it leaves `fd` open.
+fn open_stream(fd: i32, mode: &[u8]) -> io::Result<*mut libc::FILE> {
+    unsafe {
+        let copy = libc::dup(fd);
+        if copy == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let stream = libc::fdopen(copy, mode.as_ptr() as *const _);
+        if stream.is_null() {
+            let err = io::Error::last_os_error();
+            libc::close(copy);
+            return Err(err);
+        }
+        Ok(stream)
+    }
+}
 
-fn init_curses() {
+fn init_curses() -> Result<Session, MenuError> {
     // Allow unicode characters
     let locale_conf = LcCategory::all;
     setlocale(locale_conf, "en_US.UTF-8");
-    // Create curses screen
-    initscr();
+    // Anything printed before the menu must reach the terminal before curses
+    // takes it over
+    io::stdout().flush()?;
+    // Create curses screen. Unlike `initscr`, `newterm` returns null on
+    // failure instead of exiting the process.
+    let output = open_stream(libc::STDOUT_FILENO, b"w\0")?;
+    let input = match open_stream(libc::STDIN_FILENO, b"r\0") {
+        Ok(input) => input,
+        Err(err) => {
+            unsafe { libc::fclose(output) };
+            return Err(err.into());
+        }
+    };
+    let screen = newterm(None, output, input);
+    if screen.is_null() {
+        unsafe {
+            libc::fclose(output);
+            libc::fclose(input);
+        }
+        return Err(MenuError::Terminal);
+    }
     // Use default color background
     use_default_colors();
     // Do not show typed characters on screen
     noecho();
     // Allow colors
     start_color();
+    init_colors();
+
+    // Hide cursor
+    curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+
+    raw();
+    keypad(stdscr(), true);
+    Ok(Session {
+        screen,
+        output,
+        input,
+    })
+}
+
+fn init_colors() {
+    // Pairs don't outlive the curses session that initialized them
+    STYLE_PAIR_COLORS.with(|pairs| pairs.borrow_mut().clear());
     // Color used to highlight hovered selection
     init_pair(1, COLOR_BLACK, COLOR_WHITE);
     // -1 means default background
     init_pair(2, COLOR_RED, -1);
     init_pair(3, COLOR_GREEN, -1);
+}
 
-    // Hide cursor
-    curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+/// Splits the text into the rows `Screen::addstr` writes it on, starting at
+/// column `column` of a pane `width` columns wide. Each row is given as
+/// `(row, column, text)`, where `column` is where its text starts.
+fn wrap_text(
+    text: &str,
+    column: usize,
+    width: usize,
+) -> Vec<(usize, usize, String)> {
+    let mut rows = Vec::new();
+    let mut column = column;
+    let mut line = String::new();
+    // width of `line`, without the cluster being built after it
+    let mut line_width = 0;
+    // the last character with the ones drawn along with it, which are
+    // measured together so that combining marks, joined emoji and flags take
+    // up as many columns as they are drawn in
+    let mut cluster = String::new();
+    for c in text.chars() {
+        if c == '\n' {
+            line.push_str(&cluster);
+            cluster.clear();
+            rows.push((rows.len(), column, mem::take(&mut line)));
+            column = 0;
+            line_width = 0;
+            continue;
+        }
+        if joins(&cluster, c) {
+            cluster.push(c);
+        } else {
+            line_width += UnicodeWidthStr::width(cluster.as_str());
+            line.push_str(&cluster);
+            cluster = c.to_string();
+        }
+        // A full line is only wrapped once more text follows it, so a
+        // newline right after it ends it instead of adding an empty row.
+        // A character wider than the pane is still drawn, on its own row.
+        let cluster_width = UnicodeWidthStr::width(cluster.as_str());
+        let alone = column == 0 && line.is_empty();
+        if column + line_width + cluster_width > width && !alone {
+            rows.push((rows.len(), column, mem::take(&mut line)));
+            column = 0;
+            line_width = 0;
+        }
+    }
+    line.push_str(&cluster);
+    rows.push((rows.len(), column, line));
+    rows
+}
+
+/// `columns` columns of `glyph`, with spaces making up for the columns a
+/// wide glyph doesn't fit in.
+fn fill_padding(glyph: char, columns: usize) -> String {
+    let width = UnicodeWidthChar::width(glyph).unwrap_or(0).max(1);
+    let mut padding = glyph.to_string().repeat(columns / width);
+    padding.push_str(&" ".repeat(columns % width));
+    padding
+}
+
+/// The columns left for an item's text out of `room` once `count` and a
+/// right aligned `marker` fit after it, with a space before the marker and
+/// the column after it left blank.
+fn marker_room(room: usize, marker: &str, count: &str) -> usize {
+    let reserved =
+        UnicodeWidthStr::width(marker) + UnicodeWidthStr::width(count) + 2;
+    room.saturating_sub(reserved)
+}
+
+/// Whether `c` is drawn along with the characters of `cluster`, like a
+/// combining mark, a part of an emoji sequence or the second half of a flag.
+fn joins(cluster: &str, c: char) -> bool {
+    let last = match cluster.chars().last() {
+        Some(last) => last,
+        None => return false,
+    };
+    let regional = |c: char| ('\u{1F1E6}'..='\u{1F1FF}').contains(&c);
+    let modifier = ('\u{1F3FB}'..='\u{1F3FF}').contains(&c);
+    let half_flag = regional(last)
+        && cluster.chars().filter(|&c| regional(c)).count() % 2 == 1;
+    UnicodeWidthChar::width(c) == Some(0)
+        || last == '\u{200D}'
+        || modifier
+        || (regional(c) && half_flag)
+}
+
+/// Shortens `line` to at most `max` display columns, ending it with `…` if
+/// anything was cut off. Wide characters are never split.
+fn truncate(line: &str, max: usize) -> String {
+    if UnicodeWidthStr::width(line) <= max {
+        return line.to_string();
+    }
+    let mut width = 0;
+    let mut short = String::new();
+    for c in line.chars() {
+        let w = UnicodeWidthChar::width(c).unwrap_or(0);
+        // Leave a column for the marker
+        if width + w + 1 > max {
+            break;
+        }
+        width += w;
+        short.push(c);
+    }
+    if max > 0 {
+        short.push('…');
+    }
+    short
+}
+
+/// Splits text into its lines, wrapping those wider than `width` columns.
+fn wrap_lines(text: &str, width: usize) -> Vec<String> {
+    let rows = wrap_text(text, 0, width);
+    rows.into_iter().map(|(_, _, line)| line).collect()
+}
 
+/// Writes `raw` to the terminal at the given row and column, bypassing
+/// curses.
+fn write_raw(y: i32, x: i32, raw: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b[{};{}H{}", y + 1, x + 1, raw)?;
+    stdout.flush()
+}
+
+/// Restores the terminal and makes sure everything curses wrote has reached
+/// it, so output printed after the menu shows up cleanly below it.
+fn end_curses(session: Session) {
+    endwin();
+    delscreen(session.screen);
+    unsafe {
+        // Closing the streams flushes them
+        libc::fclose(session.output);
+        libc::fclose(session.input);
+    }
+    let _ = io::stdout().flush();
+}
+
+/// The state of a host application's curses session, saved while the menu
+/// borrows it.
+struct HostCurses {
+    screen: WINDOW,
+    cursor: Option<CURSOR_VISIBILITY>,
+    keypad: bool,
+    // (pair, foreground, background) of every pair the menu may overwrite
+    pairs: Vec<(i16, i16, i16)>,
+}
+
+fn borrow_curses() -> Result<HostCurses, MenuError> {
+    if stdscr().is_null() {
+        return Err(MenuError::NoHostCurses);
+    }
+    // Save the terminal modes so they can be restored with `reset_prog_mode`
+    def_prog_mode();
+
+    let last = (STYLE_PAIR_OFFSET + STYLE_PAIRS).min(COLOR_PAIRS() as i16);
+    let pairs = (1..last)
+        .map(|pair| {
+            let (mut fg, mut bg) = (0, 0);
+            pair_content(pair, &mut fg, &mut bg);
+            (pair, fg, bg)
+        })
+        .collect();
+    let keypad_enabled = is_keypad(stdscr());
+    let screen = dupwin(stdscr());
+
+    noecho();
     raw();
     keypad(stdscr(), true);
+    init_colors();
+    let cursor = curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+
+    Ok(HostCurses {
+        screen,
+        cursor,
+        keypad: keypad_enabled,
+        pairs,
+    })
 }
 
-fn end_curses() {
-    endwin();
+fn return_curses(host: HostCurses) {
+    for (pair, fg, bg) in host.pairs {
+        init_pair(pair, fg, bg);
+    }
+    if let Some(cursor) = host.cursor {
+        curs_set(cursor);
+    }
+    keypad(stdscr(), host.keypad);
+    timeout(-1);
+    reset_prog_mode();
+
+    overwrite(host.screen, stdscr());
+    delwin(host.screen);
+    touchwin(stdscr());
+    refresh();
+}
+
+/// Builds a menu with `build` and shows it on a thread of its own, so the
+/// calling thread, e.g. an async executor, isn't blocked. The result of
+/// `try_show` is sent on the returned channel when the menu closes.
+///
+/// Curses isn't thread safe, so the menu is built, shown and torn down on
+/// that one thread, and only one menu may be on the screen at a time.
+/// Nothing else should draw to the terminal or read from it until the
+/// result has arrived.
+///
+/// ```no_run
+/// let result = youchoose::spawn_menu(|| youchoose::Menu::new(0..100));
+/// // Do other work, or poll with `try_recv`
+/// match result.recv().unwrap() {
+///     Ok(choice) => println!("Chose {:?}", choice),
+///     Err(err) => eprintln!("Could not show menu: {}", err),
+/// }
+/// ```
+pub fn spawn_menu<F, I, D>(build: F) -> Receiver<Result<Vec<usize>, MenuError>>
+where
+    F: FnOnce() -> Menu<'static, I, D> + Send + 'static,
+    I: Iterator<Item = D>,
+    D: fmt::Display,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let result = build().try_show();
+        // The caller may have stopped waiting for the result
+        let _ = sender.send(result);
+    });
+    receiver
+}
+
+/// Whether the terminal is likely to display unicode, judging by the
+/// locale settings in the environment. The default icons are plain ASCII
+/// when it is not.
+///
+/// ```no_run
+/// let icon = if youchoose::supports_unicode() { "→" } else { "-" };
+/// let choice = youchoose::Menu::new(0..100).icon(icon).show();
+/// ```
+pub fn supports_unicode() -> bool {
+    if std::env::var_os("TERM").is_some_and(|term| term == "dumb") {
+        return false;
+    }
+    // The first variable that is set decides, as in setlocale(3)
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty());
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        }
+        None => false,
+    }
+}
+
+/// Returns a preview function that runs `cmd` with `args`, followed by the
+/// item, and shows its output. If the command can't be run or fails, its
+/// error output and exit status are shown instead, so a failing command
+/// never brings down the menu.
+///
+/// ```no_run
+/// let files = vec!["Cargo.toml", "README.md"];
+/// let choice = youchoose::Menu::new(files.into_iter())
+///     .preview(youchoose::shell_preview("head", &["-n", "20"]))
+///     .show();
+/// ```
+pub fn shell_preview<D>(cmd: &str, args: &[&str]) -> impl Fn(D) -> String
+where
+    D: fmt::Display,
+{
+    let cmd = cmd.to_string();
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    move |item: D| {
+        let output = match Command::new(&cmd)
+            .args(&args)
+            .arg(item.to_string())
+            .output()
+        {
+            Ok(output) => output,
+            Err(err) => return format!("could not run `{}`: {}", cmd, err),
+        };
+        let mut parts =
+            vec![String::from_utf8_lossy(&output.stdout).into_owned()];
+        if !output.status.success() {
+            parts.push(String::from_utf8_lossy(&output.stderr).into_owned());
+            parts.push(format!("`{}` {}", cmd, output.status));
+        }
+        // Each part starts on a line of its own
+        let parts: Vec<&str> = parts
+            .iter()
+            .map(|part| part.trim_end_matches('\n'))
+            .filter(|part| !part.is_empty())
+            .collect();
+        parts.join("\n")
+    }
+}
+
+/// Converts a human readable key spec into an `ncurses` keycode.
+///
+/// A spec is either a single character (`"j"`, `"/"`), a control
+/// combination (`"ctrl-n"`, `"c-n"`), or one of the names `up`, `down`,
+/// `left`, `right`, `enter`, `esc`, `space`, `tab`, `backspace`, `delete`,
+/// `insert`, `home`, `end`, `pageup`, `pagedown` and `f1` through `f12`.
+/// Names are case insensitive. Returns `None` if the spec is not recognized.
+///
+/// ```
+/// assert_eq!(youchoose::parse_key("j"), Some('j' as i32));
+/// assert_eq!(youchoose::parse_key("ctrl-n"), Some(14));
+/// assert_eq!(youchoose::parse_key("down"), Some(ncurses::KEY_DOWN));
+/// assert_eq!(youchoose::parse_key("hyper-x"), None);
+/// ```
+pub fn parse_key(spec: &str) -> Option<i32> {
+    let mut chars = spec.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(c as i32);
+    }
+
+    let lower = spec.to_lowercase();
+    for prefix in &["ctrl-", "ctrl+", "c-", "^"] {
+        if let Some(rest) = lower.strip_prefix(prefix) {
+            return match rest.as_bytes() {
+                [c] if c.is_ascii_lowercase() || b"@[\\]^_".contains(c) => {
+                    Some((c & 0x1f) as i32)
+                }
+                _ => None,
+            };
+        }
+    }
+
+    let key = match lower.as_str() {
+        "up" => KEY_UP,
+        "down" => KEY_DOWN,
+        "left" => KEY_LEFT,
+        "right" => KEY_RIGHT,
+        "enter" | "return" => 10,
+        "esc" | "escape" => 27,
+        "space" => 32,
+        "tab" => 9,
+        "backspace" => KEY_BACKSPACE,
+        "delete" | "del" => KEY_DC,
+        "insert" | "ins" => KEY_IC,
+        "home" => KEY_HOME,
+        "end" => KEY_END,
+        "pageup" | "pgup" => KEY_PPAGE,
+        "pagedown" | "pgdn" => KEY_NPAGE,
+        f => match f.strip_prefix('f').map(str::parse::<u8>) {
+            Some(Ok(n)) if (1..=12).contains(&n) => KEY_F(n),
+            _ => return None,
+        },
+    };
+    Some(key)
+}
+
+fn expect_key(spec: &str) -> i32 {
+    parse_key(spec)
+        .unwrap_or_else(|| panic!("Unrecognized key spec {:?}", spec))
+}
+
+/// Quotes `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                quoted.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A short description of an action for the help line.
+fn action_name(action: Action) -> &'static str {
+    match action {
+        Action::Down => "down",
+        Action::Up => "up",
+        Action::Select => "select",
+        Action::Multiselect => "toggle",
+        Action::ClearSelection => "clear",
+        Action::AcceptSelection => "accept",
+        Action::AcceptCurrent => "accept current",
+        Action::Copy => "copy",
+        Action::Quit => "quit",
+        Action::SetMode(Mode::Normal) => "back",
+        Action::SetMode(Mode::Search) => "search",
+        Action::SetMode(Mode::Custom(name)) => name,
+        Action::Expand => "open",
+        Action::Collapse => "close",
+        Action::Undo => "undo",
+        Action::Redo => "redo",
+        Action::QuickLook => "look",
+        Action::Increment => "more",
+        Action::Decrement => "fewer",
+        Action::PutBack => "put back",
+        Action::NextSelected => "next selected",
+        Action::PrevSelected => "previous selected",
+        Action::Preview => "preview",
+    }
+}
+
+/// A short human readable name for an `ncurses` keycode.
+fn key_name(key: i32) -> String {
+    match key {
+        KEY_UP => "↑".to_string(),
+        KEY_DOWN => "↓".to_string(),
+        KEY_LEFT => "←".to_string(),
+        KEY_RIGHT => "→".to_string(),
+        10 | KEY_ENTER => "⏎".to_string(),
+        // Terminals disagree on which code backspace sends
+        KEY_BACKSPACE | 127 | 8 => "⌫".to_string(),
+        27 => "esc".to_string(),
+        32 => "space".to_string(),
+        9 => "tab".to_string(),
+        // Ctrl-A through Ctrl-Z and Ctrl-\ through Ctrl-_
+        0..=31 => format!("^{}", (b'@' + key as u8) as char),
+        33..=126 => (key as u8 as char).to_string(),
+        _ => format!("<{}>", key),
+    }
+}
+
+/// Pipes `text` into the first clipboard command that succeeds.
+#[cfg(feature = "clipboard")]
+fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    use std::process::{Command, Stdio};
+
+    let commands: &[&[&str]] = &[
+        &["pbcopy"],
+        &["wl-copy"],
+        &["xclip", "-selection", "clipboard"],
+        &["xsel", "--clipboard", "--input"],
+    ];
+    for command in commands {
+        let child = Command::new(command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        // e.g. `wl-copy` fails outside of Wayland, so try the next one
+        if child.wait()?.success() {
+            return Ok(());
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no clipboard command available",
+    ))
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn copy_to_clipboard(_text: &str) -> io::Result<()> {
+    Err(io::Error::other("the clipboard feature is disabled"))
+}
+
+/// Makes room for an inline menu of `rows` lines below the cursor and returns
+/// the frame `(first row, number of rows)` it should be drawn in.
+fn reserve_inline_rows(rows: usize) -> io::Result<(i32, i32)> {
+    let rows = rows as i32;
+    let mut stdout = io::stdout();
+    // Scroll the terminal if the cursor is too close to the bottom, then
+    // return to the original line.
+    write!(stdout, "{}\x1b[{}A\r", "\n".repeat(rows as usize), rows)?;
+    stdout.flush()?;
+
+    // Curses takes the rows to be blank
+    let top = cursor_row().unwrap_or(0);
+    blank_rows(top, rows)?;
+    Ok((top, rows))
+}
+
+/// Blanks `rows` rows of the terminal from row `top` down, and leaves the
+/// cursor at the start of the first one.
+fn blank_rows(top: i32, rows: i32) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b[{};1H", top + 1)?;
+    for row in 0..rows {
+        if row > 0 {
+            write!(stdout, "\x1b[B")?;
+        }
+        write!(stdout, "\x1b[2K")?;
+    }
+    write!(stdout, "\x1b[{};1H", top + 1)?;
+    stdout.flush()
+}
+
+/// Keeps an inline menu on the terminal's main screen, so the rest of the
+/// terminal is left as it was. `newterm` queues up the switch to the
+/// alternate screen, so this sends it and switches straight back. The
+/// capabilities curses uses to switch screens and to clear the whole
+/// terminal are blanked, so from then on it only writes to the rows it draws
+/// on, which must have been blanked beforehand.
+fn stay_on_main_screen() {
+    let rmcup = string_capability(b"rmcup\0")
+        .map(|cap| unsafe { CStr::from_ptr(cap) }.to_bytes().to_vec());
+    for name in &[&b"smcup\0"[..], b"rmcup\0", b"clear\0"] {
+        if let Some(cap) = string_capability(name) {
+            unsafe { *cap = 0 };
+        }
+    }
+    if let Some(rmcup) = rmcup {
+        mv(0, 0);
+        refresh();
+        // Leaving the alternate screen restores the cursor, so put it back
+        // where curses left it
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(&rmcup);
+        let _ = stdout.write_all(b"\x1b[H");
+        let _ = stdout.flush();
+    }
+}
+
+/// The terminal's string capability with the given nul terminated name, if
+/// it has it.
+fn string_capability(name: &[u8]) -> Option<*mut libc::c_char> {
+    let cap = unsafe { ncurses::ll::tigetstr(name.as_ptr() as *mut _) };
+    // Null if the terminal lacks it, -1 if it isn't a string capability
+    if cap.is_null() || cap as isize == -1 {
+        return None;
+    }
+    Some(cap)
+}
+
+/// Asks the terminal for the cursor's (0-based) row using the DSR escape
+/// sequence.
+fn cursor_row() -> Option<i32> {
+    let fd = libc::STDIN_FILENO;
+    if unsafe { libc::isatty(fd) } != 1 {
+        return None;
+    }
+
+    let mut orig: libc::termios = unsafe { mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut orig) } != 0 {
+        return None;
+    }
+    let mut raw = orig;
+    unsafe { libc::cfmakeraw(&mut raw) };
+    raw.c_cc[libc::VMIN] = 0;
+    raw.c_cc[libc::VTIME] = 5; // give up after half a second
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) };
+
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(b"\x1b[6n");
+    let _ = stdout.flush();
+
+    // Response has the form ESC [ row ; col R
+    let mut response = Vec::new();
+    let mut byte = 0u8;
+    while unsafe { libc::read(fd, &mut byte as *mut u8 as *mut _, 1) } == 1 {
+        if byte == b'R' {
+            break;
+        }
+        response.push(byte);
+    }
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &orig) };
+
+    let response = String::from_utf8(response).ok()?;
+    let row = response.trim_start_matches("\x1b[").split(';').next()?;
+    row.parse::<i32>().ok().map(|row| row - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(rows: &[(usize, usize, &str)]) -> Vec<(usize, usize, String)> {
+        rows.iter()
+            .map(|&(row, column, text)| (row, column, text.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn wrap_text_exactly_filling_a_line() {
+        assert_eq!(wrap_text("abcd", 0, 4), rows(&[(0, 0, "abcd")]));
+        assert_eq!(wrap_text("cd", 2, 4), rows(&[(0, 2, "cd")]));
+        assert_eq!(
+            wrap_text("abcdef", 0, 4),
+            rows(&[(0, 0, "abcd"), (1, 0, "ef")])
+        );
+    }
+
+    #[test]
+    fn wrap_text_newline_at_the_end_of_a_line() {
+        assert_eq!(
+            wrap_text("abcd\nef", 0, 4),
+            rows(&[(0, 0, "abcd"), (1, 0, "ef")])
+        );
+        assert_eq!(
+            wrap_text("cd\nef", 2, 4),
+            rows(&[(0, 2, "cd"), (1, 0, "ef")])
+        );
+        assert_eq!(
+            wrap_text("abcd\n", 0, 4),
+            rows(&[(0, 0, "abcd"), (1, 0, "")])
+        );
+    }
+
+    #[test]
+    fn wrap_text_consecutive_newlines() {
+        assert_eq!(
+            wrap_text("ab\n\ncd", 0, 4),
+            rows(&[(0, 0, "ab"), (1, 0, ""), (2, 0, "cd")])
+        );
+        assert_eq!(
+            wrap_text("\n\n", 1, 4),
+            rows(&[(0, 1, ""), (1, 0, ""), (2, 0, "")])
+        );
+    }
+
+    #[test]
+    fn wrap_text_keeps_combining_marks_with_their_letter() {
+        let letter = "e\u{301}";
+        assert_eq!(wrap_text(&letter.repeat(4), 0, 4).len(), 1);
+        assert_eq!(
+            wrap_text(&letter.repeat(5), 0, 4),
+            rows(&[(0, 0, &letter.repeat(4)), (1, 0, letter)])
+        );
+    }
+
+    #[test]
+    fn wrap_text_keeps_flags_whole() {
+        let flag = "\u{1F1FA}\u{1F1F8}";
+        assert_eq!(
+            wrap_text(&flag.repeat(2), 0, 3),
+            rows(&[(0, 0, flag), (1, 0, flag)])
+        );
+        assert_eq!(wrap_text(&flag.repeat(2), 0, 4).len(), 1);
+    }
+
+    #[test]
+    fn wrap_text_keeps_joined_emoji_whole() {
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(
+            wrap_text(&format!("ab{}", family), 0, 3),
+            rows(&[(0, 0, "ab"), (1, 0, family)])
+        );
+        assert_eq!(
+            wrap_text(&format!("a{}", family), 0, 3),
+            rows(&[(0, 0, &format!("a{}", family))])
+        );
+    }
+
+    fn assert_within(pane: (i32, i32, i32, i32), rows: i32, columns: i32) {
+        let (top, left, bottom, right) = pane;
+        assert!(0 <= top && top <= bottom && bottom <= rows, "{:?}", pane);
+        assert!(0 <= left && left <= right && right <= columns, "{:?}", pane);
+    }
+
+    #[test]
+    fn preview_fits_in_a_narrow_terminal() {
+        let sides = [
+            ScreenSide::Left,
+            ScreenSide::Right,
+            ScreenSide::Top,
+            ScreenSide::Bottom,
+        ];
+        for &side in sides.iter() {
+            let mut menu = Menu::new(0..10)
+                .title("numbers")
+                .preview(|n| n.to_string())
+                .preview_pos(side, 0.5);
+            for columns in 0..10 {
+                for rows in 0..6 {
+                    let layout = menu.layout(rows, columns);
+                    assert_within(layout.menu, rows, columns);
+                    assert_within(layout.preview.unwrap(), rows, columns);
+
+                    // The padding inside the border doesn't turn it inside out
+                    let size = Pair {
+                        y: rows,
+                        x: columns,
+                    };
+                    let reserved = menu.reserved_rows(&size);
+                    let prev = menu.preview.as_mut().unwrap();
+                    prev.set_reserved(reserved);
+                    prev.screen.bounds = prev.screen.bounds_in(&size);
+                    prev.update_bounds();
+                    let (top_left, bottom_right) = &prev.screen.bounds;
+                    assert!(top_left.y <= bottom_right.y);
+                    assert!(top_left.x <= bottom_right.x);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn toggle_loads_the_item_and_ignores_missing_ones() {
+        let mut menu = Menu::new(0..10).multiselect();
+        menu.toggle(3);
+        assert_eq!(menu.selection, vec![3]);
+        assert!(menu.state.items[3].chosen);
+
+        menu.toggle(10);
+        assert_eq!(menu.selection, vec![3]);
+
+        menu.toggle(3);
+        assert!(menu.selection.is_empty());
+        assert!(!menu.state.items[3].chosen);
+    }
+
+    #[test]
+    fn threaded_previews_are_polled_and_failures_not_kept() {
+        let mut menu = Menu::new(0..2).preview_threaded(|n| {
+            assert!(n == 0, "no preview for {}", n);
+            n.to_string()
+        });
+        menu.yield_item(1);
+        for i in 0..2 {
+            menu.load_preview(i);
+            assert!(menu.state.items[i].preview.is_none());
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while menu.state.items[0].preview.is_none()
+            && Instant::now() < deadline
+        {
+            thread::sleep(Duration::from_millis(1));
+            menu.load_preview(0);
+        }
+        let text = menu.state.items[0].preview.as_ref().unwrap();
+        assert_eq!(text[0].0, "0");
+        assert_eq!(menu.preview_placeholder(0), None);
+
+        while menu.preview_placeholder(1) != Some("preview failed")
+            && Instant::now() < deadline
+        {
+            thread::sleep(Duration::from_millis(1));
+            menu.load_preview(1);
+        }
+        assert_eq!(menu.preview_placeholder(1), Some("preview failed"));
+        assert!(menu.state.items[1].preview.is_none());
+    }
+
+    #[test]
+    fn suffix_marker_keeps_chosen_items_on_one_row() {
+        let mut menu = Menu::new(vec!["a rather long item"].into_iter())
+            .multiselect()
+            .marker_position(MarkerPosition::Suffix);
+        menu.yield_item(0);
+        assert_eq!(menu.item_height(&menu.state.items[0], 12), 2);
+
+        menu.toggle(0);
+        assert_eq!(menu.item_height(&menu.state.items[0], 12), 1);
+    }
+
+    #[test]
+    fn wrap_lines_counts_display_width() {
+        assert_eq!(wrap_lines("日本語の字", 4), vec!["日本", "語の", "字"]);
+        assert_eq!(wrap_lines("ab\n\ncd", 4), vec!["ab", "", "cd"]);
+    }
+
+    #[test]
+    fn title_wraps_by_display_width() {
+        let menu = Menu::new(0..1).title("日本語の字");
+        let size = Pair { y: 10, x: 4 };
+        assert_eq!(menu.title_lines_in(&size), vec!["日本", "語の", "字"]);
+    }
+
+    #[test]
+    fn bound_keys_are_not_free() {
+        let menu = Menu::new(0..1)
+            .add_up_key('u' as i32)
+            .bind_undo("a")
+            .bind_put_back("b");
+        for c in "qjkuab".chars() {
+            assert!(!menu.key_is_free(c as i32), "{} is free", c);
+        }
+        assert!(menu.key_is_free('c' as i32));
+    }
+
+    #[test]
+    fn accept_single_keeps_toggled_items() {
+        let items = vec!["apple", "banana", "cherry"];
+        let mut menu = Menu::new(items.into_iter())
+            .multiselect()
+            .auto_accept_single();
+        menu.toggle(0);
+        menu.state.query = "ban".to_string();
+        menu.apply_filters();
+        assert!(matches!(menu.accept_single(), Done));
+        assert_eq!(menu.selection, vec![0, 1]);
+        assert!(menu.state.items[1].chosen);
+    }
+
+    #[test]
+    fn dual_menu_panes_line_up() {
+        let left = Menu::new(0..10).title("left").search();
+        let right = Menu::new(0..10).show_help();
+        let mut dual = DualMenu::new(left, right);
+        dual.share_rows(&Pair { y: 24, x: 80 });
+
+        let left = dual.left.layout(24, 80).menu;
+        let right = dual.right.layout(24, 80).menu;
+        assert_eq!((left.0, left.2), (2, 23));
+        assert_eq!((right.0, right.2), (2, 23));
+        assert!(left.3 <= right.1);
+    }
+
+    #[test]
+    fn select_key_toggles_only_the_last_selected_item() {
+        let mut menu = Menu::new(0..3)
+            .multiselect()
+            .select_key_toggles_last()
+            .record_history();
+        menu.toggle(0);
+        menu.toggle(1);
+
+        // Item 0 was not selected last, so the selection is accepted
+        assert!(matches!(menu.select_item(), Done));
+        assert_eq!(menu.selection, vec![0, 1]);
+
+        menu.state.hover = 1;
+        assert!(matches!(menu.select_item(), Pass));
+        assert_eq!(menu.selection, vec![0]);
+        assert!(!menu.state.items[1].chosen);
+        assert_eq!(menu.history(), &[(0, true), (1, true), (1, false)]);
+
+        assert!(matches!(menu.select_item(), Done));
+        assert_eq!(menu.selection, vec![0, 1]);
+        assert!(menu.state.items[1].chosen);
+    }
+
+    #[test]
+    fn layout_leaves_room_for_the_title_prompt_and_help() {
+        let menu = Menu::new(0..10).title("one\ntwo").search().show_help();
+        let layout = menu.layout(24, 80);
+        assert_eq!(layout.menu, (3, 0, 23, 80));
+        assert_eq!(layout.preview, None);
+    }
+
+    #[test]
+    fn layout_splits_the_preview_off() {
+        let menu = Menu::new(0..10)
+            .preview(|n| n.to_string())
+            .preview_pos(ScreenSide::Bottom, 0.25);
+        let layout = menu.layout(40, 80);
+        assert_eq!(layout.menu, (0, 0, 30, 80));
+        assert_eq!(layout.preview, Some((31, 0, 40, 80)));
+    }
+
+    #[test]
+    fn layout_fits_tiny_terminals() {
+        let menu = Menu::new(0..10).title("numbers").search().show_help();
+        for rows in 0..4 {
+            for columns in 0..4 {
+                let layout = menu.layout(rows, columns);
+                assert_within(layout.menu, rows, columns);
+            }
+        }
+    }
+
+    #[test]
+    fn streamed_items_are_added_without_waiting() {
+        let slow =
+            (0..3).inspect(|_| thread::sleep(Duration::from_millis(50)));
+        let mut menu = Menu::new_streaming(slow);
+        menu.screen.bounds = menu.screen.bounds_in(&Pair { y: 24, x: 80 });
+
+        let start = Instant::now();
+        menu.yield_for(Duration::from_secs(10));
+        assert!(start.elapsed() < Duration::from_millis(50));
+        assert!(menu.state.items.is_empty());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !menu.state.exhausted && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+            menu.yield_for(Duration::from_secs(10));
+        }
+        assert!(menu.state.exhausted);
+        assert_eq!(menu.state.items.len(), 3);
+    }
+
+    #[test]
+    fn ranking_an_endless_iterator_stops_at_the_limit() {
+        let mut menu = Menu::new(0..).rank_matches();
+        menu.state.query = "7".to_string();
+        menu.apply_filters();
+        assert_eq!(menu.state.items.len(), RANK_LIMIT);
+        let best = menu.current().map(|i| menu.state.items[i].index);
+        assert_eq!(best, Some(7));
+    }
+
+    #[test]
+    fn fill_padding_fits_the_columns() {
+        assert_eq!(fill_padding(' ', 3), "   ");
+        assert_eq!(fill_padding('·', 3), "···");
+        assert_eq!(fill_padding('＊', 5), "＊＊ ");
+    }
+
+    #[test]
+    fn transfer_leaves_room_for_the_picked_items() {
+        let menu = Menu::new(0..3).transfer();
+        assert_eq!(menu.layout(24, 80).menu, (0, 0, 24, 40));
+        let picked = menu.picked.as_ref().unwrap();
+        let (top_left, bottom_right) =
+            picked.bounds_in(&Pair { y: 24, x: 80 });
+        assert_eq!((top_left.x, bottom_right.x), (41, 80));
+
+        let menu = Menu::new(0..3).transfer().preview(|n| n.to_string());
+        assert!(menu.picked.is_none());
+    }
+
+    #[test]
+    fn shell_preview_puts_each_part_on_its_own_line() {
+        let script = "printf out; printf err >&2; exit 3";
+        let preview = shell_preview("sh", &["-c", script]);
+        assert_eq!(preview("item"), "out\nerr\n`sh` exit status: 3");
+    }
+
+    #[test]
+    fn item_indices_leave_out_the_quit_sentinel() {
+        let mut menu =
+            Menu::new_with_meta(vec![("a", 1), ("b", 2)].into_iter())
+                .default_on_quit(DefaultChoice::Index(usize::MAX));
+        menu.yield_item(1);
+        menu.state.quit = true;
+        let selection = menu.selection();
+        assert_eq!(selection, vec![usize::MAX]);
+        assert!(menu.item_indices(selection).is_empty());
+        assert_eq!(menu.item_indices(vec![1, 0]), vec![1, 0]);
+    }
+
+    #[test]
+    fn json_string_escapes_quotes_and_backslashes() {
+        assert_eq!(json_string(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(json_string(r"C:\dir"), r#""C:\\dir""#);
+    }
+
+    #[test]
+    fn json_string_escapes_control_characters() {
+        assert_eq!(json_string("a\nb\tc\r"), r#""a\nb\tc\r""#);
+        assert_eq!(json_string("\u{1}\u{1f}"), r#""\u0001\u001f""#);
+    }
+
+    #[test]
+    fn json_string_keeps_non_ascii() {
+        assert_eq!(json_string("café 日本 🎉"), "\"café 日本 🎉\"");
+    }
+
+    #[test]
+    fn toggles_find_sorted_items_by_index() {
+        let mut menu = Menu::new(0..3).multiselect().sort_by(|a, b| b.cmp(a));
+        menu.yield_sorted();
+        menu.toggle(0);
+        assert_eq!(menu.selection, vec![0]);
+        assert!(menu.state.items[2].chosen);
+        assert!(!menu.state.items[0].chosen);
+
+        menu.undo_toggle();
+        assert!(menu.selection.is_empty());
+        assert!(!menu.state.items[2].chosen);
+    }
+
+    #[test]
+    fn count_changes_are_logged_once() {
+        let mut menu =
+            Menu::new(0..3).multiselect().quantities().record_history();
+        menu.yield_item(0);
+        menu.change_count(2);
+        menu.change_count(-1);
+        assert_eq!(menu.history(), &[(0, true)]);
+        menu.change_count(-1);
+        assert_eq!(menu.history(), &[(0, true), (0, false)]);
+        assert_eq!(menu.undo.len(), 2);
+    }
+
+    #[test]
+    fn initial_query_sees_sorted_items() {
+        let items = vec!["bc", "ab", "bb"];
+        let mut menu = Menu::new(items.into_iter())
+            .query("b")
+            .auto_accept_single()
+            .sort_by(|a, b| a.cmp(b));
+        assert!(!menu.accept_initial());
+        let order: Vec<usize> =
+            menu.state.items.iter().map(|item| item.index).collect();
+        assert_eq!(order, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn inline_without_rows_is_an_error() {
+        let mut menu = Menu::new(0..3).inline(0);
+        assert!(matches!(menu.try_show(), Err(MenuError::NoRows)));
+    }
+
+    #[test]
+    fn accept_current_deselects_the_toggled_items() {
+        let mut menu = Menu::new(0..3).multiselect().record_history();
+        menu.toggle(0);
+        menu.toggle(2);
+        menu.state.hover = 1;
+        assert!(matches!(menu.accept_current(), Done));
+        assert_eq!(menu.selection, vec![1]);
+        let chosen: Vec<bool> =
+            menu.state.items.iter().map(|item| item.chosen).collect();
+        assert_eq!(chosen, vec![false, true, false]);
+        assert_eq!(&menu.history()[2..], &[(0, false), (2, false), (1, true)]);
+    }
 }